@@ -0,0 +1,658 @@
+use rand::{
+    seq::SliceRandom,
+    Rng,
+    SeedableRng,
+};
+use rand_chacha::ChaCha8Rng;
+use std::time::Duration;
+
+use super::{
+    symbols::lookalikes,
+    DistractorKind,
+    GameMode,
+    PenaltyPolicy,
+    RunGoal,
+    SequenceRule,
+    ShuffleMode,
+    SymbolSet,
+};
+
+// Sequence a tile belongs to, only the Gorbov mode uses red tiles
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TileColor {
+    #[default]
+    Black,
+    Red,
+}
+
+// Content of a tile, the same number can appear once per color
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Target {
+    pub number: u16,
+    pub color: TileColor,
+}
+
+impl Target {
+    // Text written on the tile
+    pub fn label(&self, symbols: SymbolSet, custom_symbols: &[String]) -> String {
+        symbols.label(self.number, custom_symbols)
+    }
+
+    // Description of the target outside of the table, naming the color where it matters
+    pub fn describe(&self, symbols: SymbolSet, custom_symbols: &[String]) -> String {
+        match self.color {
+            TileColor::Black => self.label(symbols, custom_symbols),
+            TileColor::Red => format!("{} (red)", self.label(symbols, custom_symbols)),
+        }
+    }
+}
+
+// Content of a cell, distractors have no target and clicking them is a mistake
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub target: Option<Target>,
+    pub label: String,
+    pub color: TileColor,
+}
+
+// Everything that decides how a run is played, independent of how it is displayed
+#[derive(Clone, Debug)]
+pub struct BoardRules {
+    pub width: usize,
+    pub height: usize,
+    pub mode: GameMode,
+    pub sequence: SequenceRule,
+    pub symbols: SymbolSet,
+    pub custom_symbols: Vec<String>,
+    pub distractors: usize,
+    pub distractor_kind: DistractorKind,
+    pub shuffle: ShuffleMode,
+    pub penalty_policy: PenaltyPolicy,
+    pub incorrect_penalty: u8,
+    pub penalty_percent: u8,
+    pub goal: RunGoal,
+    pub time_limit: u16,
+    pub mistake_limit: u32,
+}
+
+// A classic 5x5 table of numbers
+impl Default for BoardRules {
+    fn default() -> Self {
+        BoardRules {
+            width: 5,
+            height: 5,
+            mode: GameMode::Classic,
+            sequence: SequenceRule::default(),
+            symbols: SymbolSet::Numbers,
+            custom_symbols: Vec::new(),
+            distractors: 0,
+            distractor_kind: DistractorKind::OutOfRange,
+            shuffle: ShuffleMode::Off,
+            penalty_policy: PenaltyPolicy::Flat,
+            incorrect_penalty: 2,
+            penalty_percent: 5,
+            goal: RunGoal::FinishTable,
+            time_limit: 60,
+            mistake_limit: 3,
+        }
+    }
+}
+
+impl BoardRules {
+    // Distractors actually placed, a table is never more than half distractors
+    pub fn distractor_count(&self) -> usize {
+        self.distractors.min(self.width * self.height / 2)
+    }
+
+    // Tiles of the table that belong to the sequence
+    pub fn target_count(&self) -> usize {
+        self.width * self.height - self.distractor_count()
+    }
+
+    fn label(&self, target: Target) -> String {
+        target.label(self.symbols, &self.custom_symbols)
+    }
+}
+
+// Result of clicking a cell
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClickOutcome {
+    // The expected tile was found
+    Correct(Target),
+    // A wrong tile or a distractor, `restarted` when the sequence has to be found again
+    Mistake {
+        penalty: Duration,
+        restarted: bool,
+    },
+    // Cells outside the table and clicks after it was completed change nothing
+    Ignored,
+}
+
+// State and rules of a run, which may span several tables depending on its goal.
+// All randomness comes from the seed, so the same seed and clicks always give the same run.
+pub struct SchulteBoard {
+    rules: BoardRules,
+    seed: u64,
+    // A named algorithm instead of `StdRng`, whose output may change between rand versions and platforms
+    rng: ChaCha8Rng,
+    // Order in which the targets have to be clicked
+    targets: Vec<Target>,
+    // Tiles row by row
+    cells: Vec<Tile>,
+    // Index into `targets` of the tile to click next
+    next: usize,
+    // Numbers found over all tables of the run
+    found: u32,
+    mistakes: u32,
+    // Mistakes since the last correct click
+    streak: u32,
+    // Time added to the score for mistakes
+    penalty: Duration,
+}
+
+impl SchulteBoard {
+    pub fn new(rules: BoardRules, seed: u64) -> Self {
+        let mut board = SchulteBoard {
+            rules,
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            targets: Vec::new(),
+            cells: Vec::new(),
+            next: 0,
+            found: 0,
+            mistakes: 0,
+            streak: 0,
+            penalty: Duration::ZERO,
+        };
+        board.next_table();
+        board
+    }
+
+    // Replaces the table with a freshly generated one, keeping the counters of the run
+    pub fn next_table(&mut self) {
+        self.targets = targets(&self.rules);
+        let distractors = self.distractors();
+        let mut cells: Vec<Tile> = self.targets.iter()
+            .map(|&target| Tile {
+                target: Some(target),
+                label: self.rules.label(target),
+                color: target.color,
+            })
+            .chain(distractors)
+            .collect();
+        cells.shuffle(&mut self.rng);
+        self.cells = cells;
+        self.next = 0;
+    }
+
+    pub fn rules(&self) -> &BoardRules {
+        &self.rules
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn cells(&self) -> &[Tile] {
+        &self.cells
+    }
+
+    pub fn targets(&self) -> &[Target] {
+        &self.targets
+    }
+
+    pub fn next_target(&self) -> Option<Target> {
+        self.targets.get(self.next).copied()
+    }
+
+    // Targets of the current table found so far
+    pub fn progress(&self) -> usize {
+        self.next
+    }
+
+    pub fn found(&self) -> u32 {
+        self.found
+    }
+
+    pub fn mistakes(&self) -> u32 {
+        self.mistakes
+    }
+
+    pub fn penalty(&self) -> Duration {
+        self.penalty
+    }
+
+    pub fn is_found(&self, cell: usize) -> bool {
+        let found = &self.targets[..self.next];
+        self.cells.get(cell)
+            .and_then(|tile| tile.target)
+            .is_some_and(|target| found.contains(&target))
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.next >= self.targets.len()
+    }
+
+    // `elapsed` is the time on the clock when the cell was clicked
+    pub fn click(&mut self, cell: usize, elapsed: Duration) -> ClickOutcome {
+        let (Some(tile), Some(next)) = (self.cells.get(cell), self.next_target()) else {
+            return ClickOutcome::Ignored;
+        };
+        if tile.target == Some(next) {
+            self.next += 1;
+            self.found = self.found.saturating_add(1);
+            self.streak = 0;
+            return ClickOutcome::Correct(next);
+        }
+        self.mistakes = self.mistakes.saturating_add(1);
+        self.streak = self.streak.saturating_add(1);
+        let penalty = self.mistake_penalty(elapsed);
+        self.penalty = self.penalty.saturating_add(penalty);
+        let restarted = self.rules.penalty_policy == PenaltyPolicy::RestartSequence;
+        if restarted {
+            self.found = self.found.saturating_sub(self.next as u32);
+            self.next = 0;
+        }
+        ClickOutcome::Mistake { penalty, restarted }
+    }
+
+    // Time added for the mistake just made
+    fn mistake_penalty(&self, elapsed: Duration) -> Duration {
+        let seconds = Duration::from_secs(self.rules.incorrect_penalty as u64);
+        match self.rules.penalty_policy {
+            PenaltyPolicy::Flat => seconds,
+            PenaltyPolicy::Percent => elapsed * self.rules.penalty_percent as u32 / 100,
+            PenaltyPolicy::Escalating => seconds.saturating_mul(self.streak),
+            PenaltyPolicy::RestartSequence | PenaltyPolicy::None => Duration::ZERO,
+        }
+    }
+
+    // Dynamic table: tiles trade places according to the shuffle mode.
+    // Returns the new cell of every tile, indexed by the cell it was in.
+    pub fn reshuffle(&mut self) -> Vec<usize> {
+        let moving: Vec<usize> = (0..self.cells.len())
+            .filter(|&cell| match self.rules.shuffle {
+                ShuffleMode::Off => false,
+                ShuffleMode::Remaining => !self.is_found(cell),
+                ShuffleMode::All => true,
+            })
+            .collect();
+        let mut destinations = moving.clone();
+        destinations.shuffle(&mut self.rng);
+        let mut moves: Vec<usize> = (0..self.cells.len()).collect();
+        let previous = self.cells.clone();
+        for (&from, &to) in moving.iter().zip(&destinations) {
+            moves[from] = to;
+            self.cells[to] = previous[from].clone();
+        }
+        moves
+    }
+
+    // Time remaining in a time attack, each penalty shortens the budget
+    pub fn time_left(&self, elapsed: Duration) -> Duration {
+        Duration::from_secs(self.rules.time_limit as u64)
+            .saturating_sub(elapsed)
+            .saturating_sub(self.penalty)
+    }
+
+    pub fn is_over(&self, elapsed: Duration) -> bool {
+        match self.rules.goal {
+            RunGoal::FinishTable => self.is_complete(),
+            RunGoal::TimeAttack => self.time_left(elapsed).is_zero(),
+            RunGoal::Endless => self.mistakes >= self.rules.mistake_limit,
+        }
+    }
+
+    pub fn score(&self, elapsed: Duration) -> f32 {
+        self.rules.goal.score(elapsed.as_secs_f32(), self.penalty.as_secs_f32(), self.found)
+    }
+
+    // Tiles that are not part of the sequence, with labels that cannot be confused with a target
+    fn distractors(&mut self) -> Vec<Tile> {
+        let rules = &self.rules;
+        let rng = &mut self.rng;
+        let count = rules.distractor_count();
+        let target_labels: Vec<String> = self.targets.iter().map(|&target| rules.label(target)).collect();
+        // Following numbers of the sequence, also the fallback when other kinds run out of labels
+        let step = if rules.mode.uses_sequence() { rules.sequence.step } else { 1 };
+        let last = self.targets.iter().map(|target| target.number).max().unwrap_or_default();
+        let out_of_range = (1..).map(|index| rules.symbols.label(last + index * step, &rules.custom_symbols));
+        let mut labels: Vec<String> = match rules.distractor_kind {
+            DistractorKind::OutOfRange => Vec::new(),
+            DistractorKind::OtherSymbols => {
+                let other = match rules.symbols {
+                    SymbolSet::Numbers => SymbolSet::Latin,
+                    _ => SymbolSet::Numbers,
+                };
+                let pool: Vec<u16> = (1..=(2 * count as u16).max(26)).collect();
+                pool.choose_multiple(rng, count)
+                    .map(|&number| other.label(number, &rules.custom_symbols))
+                    .collect()
+            }
+            DistractorKind::Lookalikes => {
+                let mut variants: Vec<String> = target_labels.iter()
+                    .flat_map(|label| lookalikes(label))
+                    .collect();
+                variants.sort();
+                variants.dedup();
+                variants.shuffle(rng);
+                variants
+            }
+        };
+        labels.retain(|label| !target_labels.contains(label));
+        labels.truncate(count);
+        labels.extend(out_of_range.take(count - labels.len()));
+        labels.into_iter()
+            .map(|label| Tile {
+                target: None,
+                label,
+                // Gorbov distractors are colored like either sequence
+                color: match rules.mode {
+                    GameMode::Gorbov if rng.gen() => TileColor::Red,
+                    _ => TileColor::Black,
+                },
+            })
+            .collect()
+    }
+}
+
+// Order in which the tiles of a table have to be clicked
+fn targets(rules: &BoardRules) -> Vec<Target> {
+    let cells = rules.target_count();
+    let black = |number| Target { number, color: TileColor::Black };
+    let red = |number| Target { number, color: TileColor::Red };
+    match rules.mode {
+        GameMode::Classic | GameMode::Memory => {
+            rules.sequence.numbers(cells).into_iter().map(black).collect()
+        }
+        // Black 1, red N, black 2, red N - 1, ... with one more black tile on odd tables
+        GameMode::Gorbov => {
+            let reds = cells as u16 / 2;
+            (1..=cells as u16 - reds)
+                .flat_map(|number| {
+                    let next_red = (number <= reds).then(|| red(reds + 1 - number));
+                    std::iter::once(black(number)).chain(next_red)
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell_of(board: &SchulteBoard, target: Target) -> usize {
+        board.cells().iter().position(|tile| tile.target == Some(target)).unwrap()
+    }
+
+    fn click_next(board: &mut SchulteBoard) -> ClickOutcome {
+        let cell = cell_of(board, board.next_target().unwrap());
+        board.click(cell, Duration::ZERO)
+    }
+
+    fn wrong_cell(board: &SchulteBoard) -> usize {
+        let next = board.next_target().unwrap();
+        board.cells().iter().position(|tile| tile.target != Some(next)).unwrap()
+    }
+
+    #[test]
+    fn seed_layout_is_pinned() {
+        let board = SchulteBoard::new(BoardRules::default(), 42);
+        let labels: Vec<&str> = board.cells().iter().map(|tile| tile.label.as_str()).collect();
+        // Changing this layout breaks every seed players have shared or stored with their scores
+        assert_eq!(labels, [
+            "21", "20", "1", "3", "2",
+            "9", "5", "14", "16", "22",
+            "24", "7", "23", "19", "12",
+            "11", "18", "25", "15", "13",
+            "8", "10", "4", "17", "6",
+        ]);
+    }
+
+    #[test]
+    fn classic_table_contains_every_number_once() {
+        let board = SchulteBoard::new(BoardRules::default(), 1);
+        let mut numbers: Vec<u16> = board.cells().iter().map(|tile| tile.target.unwrap().number).collect();
+        numbers.sort();
+        assert_eq!(numbers, (1..=25).collect::<Vec<_>>());
+        assert_eq!(board.cells()[cell_of(&board, board.targets()[6])].label, "7");
+    }
+
+    #[test]
+    fn same_seed_gives_same_table() {
+        let a = SchulteBoard::new(BoardRules::default(), 42);
+        let b = SchulteBoard::new(BoardRules::default(), 42);
+        let c = SchulteBoard::new(BoardRules::default(), 43);
+        assert_eq!(a.cells(), b.cells());
+        assert_ne!(a.cells(), c.cells());
+    }
+
+    #[test]
+    fn descending_sequence_starts_from_the_largest_number() {
+        let rules = BoardRules {
+            width: 2,
+            height: 2,
+            sequence: SequenceRule { start: 2, step: 2, descending: true },
+            ..BoardRules::default()
+        };
+        let board = SchulteBoard::new(rules, 0);
+        let numbers: Vec<u16> = board.targets().iter().map(|target| target.number).collect();
+        assert_eq!(numbers, [8, 6, 4, 2]);
+    }
+
+    #[test]
+    fn gorbov_alternates_black_ascending_and_red_descending() {
+        let rules = BoardRules {
+            width: 5,
+            height: 1,
+            mode: GameMode::Gorbov,
+            ..BoardRules::default()
+        };
+        let board = SchulteBoard::new(rules, 0);
+        let black = |number| Target { number, color: TileColor::Black };
+        let red = |number| Target { number, color: TileColor::Red };
+        assert_eq!(board.targets(), [black(1), red(2), black(2), red(1), black(3)]);
+    }
+
+    #[test]
+    fn finishing_the_table_in_order() {
+        let mut board = SchulteBoard::new(BoardRules::default(), 7);
+        for number in 1..=25 {
+            assert!(!board.is_complete());
+            let outcome = click_next(&mut board);
+            assert_eq!(outcome, ClickOutcome::Correct(Target { number, color: TileColor::Black }));
+        }
+        assert!(board.is_complete());
+        assert!(board.is_over(Duration::from_secs(10)));
+        assert_eq!(board.found(), 25);
+        assert_eq!(board.score(Duration::from_secs(10)), 10.0);
+        assert_eq!(board.click(0, Duration::ZERO), ClickOutcome::Ignored);
+    }
+
+    #[test]
+    fn clicks_outside_the_table_are_ignored() {
+        let mut board = SchulteBoard::new(BoardRules::default(), 7);
+        assert_eq!(board.click(25, Duration::ZERO), ClickOutcome::Ignored);
+        assert_eq!(board.mistakes(), 0);
+    }
+
+    #[test]
+    fn flat_penalty_is_added_to_the_score() {
+        let mut board = SchulteBoard::new(BoardRules::default(), 3);
+        let outcome = board.click(wrong_cell(&board), Duration::ZERO);
+        assert_eq!(outcome, ClickOutcome::Mistake { penalty: Duration::from_secs(2), restarted: false });
+        assert_eq!(board.mistakes(), 1);
+        assert_eq!(board.progress(), 0);
+        assert_eq!(board.score(Duration::from_secs(5)), 7.0);
+    }
+
+    #[test]
+    fn percent_penalty_depends_on_elapsed_time() {
+        let rules = BoardRules {
+            penalty_policy: PenaltyPolicy::Percent,
+            penalty_percent: 10,
+            ..BoardRules::default()
+        };
+        let mut board = SchulteBoard::new(rules, 3);
+        board.click(wrong_cell(&board), Duration::from_secs(30));
+        assert_eq!(board.penalty(), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn escalating_penalty_grows_with_consecutive_mistakes() {
+        let rules = BoardRules {
+            penalty_policy: PenaltyPolicy::Escalating,
+            ..BoardRules::default()
+        };
+        let mut board = SchulteBoard::new(rules, 3);
+        board.click(wrong_cell(&board), Duration::ZERO);
+        board.click(wrong_cell(&board), Duration::ZERO);
+        assert_eq!(board.penalty(), Duration::from_secs(2 + 4));
+        click_next(&mut board);
+        board.click(wrong_cell(&board), Duration::ZERO);
+        assert_eq!(board.penalty(), Duration::from_secs(2 + 4 + 2));
+    }
+
+    #[test]
+    fn penalty_accumulation_saturates() {
+        let rules = BoardRules {
+            penalty_policy: PenaltyPolicy::Escalating,
+            incorrect_penalty: u8::MAX,
+            ..BoardRules::default()
+        };
+        let mut board = SchulteBoard::new(rules, 3);
+        // Close to the limits, which a real run could never reach
+        board.penalty = Duration::MAX - Duration::from_secs(1);
+        board.mistakes = u32::MAX - 1;
+        board.streak = u32::MAX - 1;
+        for _ in 0..3 {
+            board.click(wrong_cell(&board), Duration::ZERO);
+        }
+        assert_eq!(board.penalty(), Duration::MAX);
+        assert_eq!(board.mistakes(), u32::MAX);
+        assert_eq!(board.streak, u32::MAX);
+    }
+
+    #[test]
+    fn restart_sequence_policy_loses_progress() {
+        let rules = BoardRules {
+            penalty_policy: PenaltyPolicy::RestartSequence,
+            ..BoardRules::default()
+        };
+        let mut board = SchulteBoard::new(rules, 5);
+        click_next(&mut board);
+        click_next(&mut board);
+        let outcome = board.click(wrong_cell(&board), Duration::ZERO);
+        assert_eq!(outcome, ClickOutcome::Mistake { penalty: Duration::ZERO, restarted: true });
+        assert_eq!(board.progress(), 0);
+        assert_eq!(board.found(), 0);
+        assert_eq!(board.next_target().unwrap().number, 1);
+    }
+
+    #[test]
+    fn no_penalty_policy_only_counts_mistakes() {
+        let rules = BoardRules {
+            penalty_policy: PenaltyPolicy::None,
+            ..BoardRules::default()
+        };
+        let mut board = SchulteBoard::new(rules, 5);
+        board.click(wrong_cell(&board), Duration::ZERO);
+        assert_eq!(board.mistakes(), 1);
+        assert_eq!(board.penalty(), Duration::ZERO);
+    }
+
+    #[test]
+    fn distractors_are_never_targets_and_count_as_mistakes() {
+        for distractor_kind in DistractorKind::ALL {
+            let rules = BoardRules {
+                distractors: 5,
+                distractor_kind,
+                ..BoardRules::default()
+            };
+            let mut board = SchulteBoard::new(rules, 11);
+            assert_eq!(board.cells().len(), 25);
+            assert_eq!(board.targets().len(), 20);
+            let target_labels: Vec<&str> = board.cells().iter()
+                .filter(|tile| tile.target.is_some())
+                .map(|tile| tile.label.as_str())
+                .collect();
+            let distractors: Vec<usize> = (0..25).filter(|&cell| board.cells()[cell].target.is_none()).collect();
+            assert_eq!(distractors.len(), 5);
+            for &cell in &distractors {
+                assert!(!target_labels.contains(&board.cells()[cell].label.as_str()));
+            }
+            assert!(matches!(board.click(distractors[0], Duration::ZERO), ClickOutcome::Mistake { .. }));
+        }
+    }
+
+    #[test]
+    fn distractors_never_fill_more_than_half_the_table() {
+        let rules = BoardRules {
+            width: 2,
+            height: 2,
+            distractors: 50,
+            ..BoardRules::default()
+        };
+        let board = SchulteBoard::new(rules, 0);
+        assert_eq!(board.targets().len(), 2);
+    }
+
+    #[test]
+    fn reshuffle_keeps_found_tiles_in_place() {
+        let rules = BoardRules {
+            shuffle: ShuffleMode::Remaining,
+            ..BoardRules::default()
+        };
+        let mut board = SchulteBoard::new(rules, 9);
+        click_next(&mut board);
+        let first = cell_of(&board, board.targets()[0]);
+        let before = board.cells().to_vec();
+        let moves = board.reshuffle();
+        assert_eq!(moves[first], first);
+        let mut destinations = moves.clone();
+        destinations.sort();
+        assert_eq!(destinations, (0..25).collect::<Vec<_>>());
+        for (from, &to) in moves.iter().enumerate() {
+            assert_eq!(board.cells()[to], before[from]);
+        }
+    }
+
+    #[test]
+    fn next_table_keeps_the_run_counters() {
+        let rules = BoardRules {
+            width: 2,
+            height: 2,
+            goal: RunGoal::Endless,
+            mistake_limit: 2,
+            ..BoardRules::default()
+        };
+        let mut board = SchulteBoard::new(rules, 2);
+        board.click(wrong_cell(&board), Duration::ZERO);
+        for _ in 0..4 {
+            click_next(&mut board);
+        }
+        assert!(board.is_complete());
+        assert!(!board.is_over(Duration::ZERO));
+        board.next_table();
+        assert_eq!(board.progress(), 0);
+        assert_eq!(board.found(), 4);
+        board.click(wrong_cell(&board), Duration::ZERO);
+        assert!(board.is_over(Duration::ZERO));
+        assert_eq!(board.score(Duration::from_secs(100)), 4.0);
+    }
+
+    #[test]
+    fn time_attack_penalties_shorten_the_budget() {
+        let rules = BoardRules {
+            goal: RunGoal::TimeAttack,
+            time_limit: 10,
+            ..BoardRules::default()
+        };
+        let mut board = SchulteBoard::new(rules, 2);
+        board.click(wrong_cell(&board), Duration::ZERO);
+        assert_eq!(board.time_left(Duration::from_secs(5)), Duration::from_secs(3));
+        assert!(!board.is_over(Duration::from_secs(7)));
+        assert!(board.is_over(Duration::from_secs(8)));
+    }
+}
@@ -0,0 +1,351 @@
+use bevy::prelude::*;
+use std::{fmt, fs, io};
+
+use super::{
+    project_dirs,
+    GameConfiguraiton,
+    MIN_GRID_SIZE,
+    MAX_GRID_SIZE,
+    MAX_SEQUENCE_START,
+    MAX_SEQUENCE_STEP,
+    MAX_SHUFFLE_ANIMATION,
+    MAX_MEMORY_PREVIEW,
+    MIN_GAZE_RADIUS,
+    MAX_GAZE_RADIUS,
+    MAX_AUTO_RETURN,
+    MAX_COUNTDOWN,
+    MAX_INCORRECT_PENALTY,
+    MAX_TIMER_DURATION,
+    MIN_BUTTON_SIZE,
+    MAX_BUTTON_SIZE,
+    MAX_BUTTON_PADDING,
+    MIN_FONT_SIZE,
+    MAX_FONT_SIZE,
+    MIN_TIME_LIMIT,
+    MAX_TIME_LIMIT,
+    MAX_MISTAKE_LIMIT,
+    MAX_PENALTY_PERCENT,
+    MAX_DISTRACTORS,
+    MAX_PERTURBATION,
+};
+
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+// Problems found while loading the configuration, shown to the player on the main menu
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct ConfigWarnings(Vec<String>);
+
+#[derive(Debug)]
+pub enum ConfigError {
+    NoProjectDirs,
+    Io(io::Error),
+    Serialize(toml::ser::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::NoProjectDirs => write!(f, "unable to get project directories"),
+            ConfigError::Io(error) => write!(f, "{}", error),
+            ConfigError::Serialize(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(error: io::Error) -> Self {
+        ConfigError::Io(error)
+    }
+}
+
+impl From<toml::ser::Error> for ConfigError {
+    fn from(error: toml::ser::Error) -> Self {
+        ConfigError::Serialize(error)
+    }
+}
+
+// Loads the configuration file, falling back to the default for anything missing or unusable
+pub fn load_configuration() -> (GameConfiguraiton, ConfigWarnings) {
+    let mut warnings = ConfigWarnings::default();
+    let Some(proj_dirs) = project_dirs() else {
+        warnings.push("Unable to get project directories, using default settings".to_string());
+        return (GameConfiguraiton::default(), warnings);
+    };
+    let file_path = proj_dirs.config_dir().join(CONFIG_FILE_NAME);
+    let contents = match fs::read_to_string(&file_path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            return (GameConfiguraiton::default(), warnings);
+        }
+        Err(error) => {
+            warnings.push(format!("Unable to read {}: {}", file_path.display(), error));
+            return (GameConfiguraiton::default(), warnings);
+        }
+    };
+    let config = parse_configuration(&contents, &mut warnings);
+    for warning in warnings.iter() {
+        warn!("{}", warning);
+    }
+    (config, warnings)
+}
+
+fn parse_configuration(contents: &str, warnings: &mut ConfigWarnings) -> GameConfiguraiton {
+    let file: toml::Table = match contents.parse() {
+        Ok(file) => file,
+        Err(error) => {
+            warnings.push(format!("Malformed configuration file, using default settings: {}", error));
+            return GameConfiguraiton::default();
+        }
+    };
+    let defaults = toml::Table::try_from(GameConfiguraiton::default())
+        .expect("Default configuration is serializable");
+    // Accept the fields one by one so a single bad value does not discard the others
+    let mut merged = defaults.clone();
+    for (key, value) in file {
+        if !defaults.contains_key(&key) {
+            warnings.push(format!("Unknown setting `{}` ignored", key));
+            continue;
+        }
+        let mut candidate = merged.clone();
+        candidate.insert(key.clone(), value);
+        match candidate.clone().try_into::<GameConfiguraiton>() {
+            Ok(_) => merged = candidate,
+            Err(error) => warnings.push(format!("Invalid value for `{}` ignored: {}", key, error.message())),
+        }
+    }
+    let mut config: GameConfiguraiton = merged.try_into()
+        .expect("Merged configuration only contains valid fields");
+    validate_configuration(&mut config, warnings);
+    config
+}
+
+// Brings every bounded setting back into the range the menu allows, with a warning for each change.
+// Also applied to configurations coming from elsewhere than the file, like the ones stored in replays.
+pub fn validate_configuration(config: &mut GameConfiguraiton, warnings: &mut ConfigWarnings) {
+    for (name, size) in [("width", &mut config.width), ("height", &mut config.height)] {
+        if !(MIN_GRID_SIZE..=MAX_GRID_SIZE).contains(size) {
+            warnings.push(format!(
+                "`{}` must be between {} and {}, got {}",
+                name, MIN_GRID_SIZE, MAX_GRID_SIZE, size,
+            ));
+            *size = (*size).clamp(MIN_GRID_SIZE, MAX_GRID_SIZE);
+        }
+    }
+    for (name, value, max) in [
+        ("sequence_start", &mut config.sequence_start, MAX_SEQUENCE_START),
+        ("sequence_step", &mut config.sequence_step, MAX_SEQUENCE_STEP),
+    ] {
+        if !(1..=max).contains(value) {
+            warnings.push(format!("`{}` must be between 1 and {}, got {}", name, max, value));
+            *value = (*value).clamp(1, max);
+        }
+    }
+    for (name, level) in [
+        ("perturb_rotation", &mut config.perturb_rotation),
+        ("perturb_size", &mut config.perturb_size),
+        ("perturb_color", &mut config.perturb_color),
+    ] {
+        if *level > MAX_PERTURBATION {
+            warnings.push(format!("`{}` must be at most {}, got {}", name, MAX_PERTURBATION, level));
+            *level = MAX_PERTURBATION;
+        }
+    }
+    if config.distractors > MAX_DISTRACTORS {
+        warnings.push(format!(
+            "`distractors` must be at most {}, got {}",
+            MAX_DISTRACTORS, config.distractors,
+        ));
+        config.distractors = MAX_DISTRACTORS;
+    }
+    if !(1..=MAX_PENALTY_PERCENT).contains(&config.penalty_percent) {
+        warnings.push(format!(
+            "`penalty_percent` must be between 1 and {}, got {}",
+            MAX_PENALTY_PERCENT, config.penalty_percent,
+        ));
+        config.penalty_percent = config.penalty_percent.clamp(1, MAX_PENALTY_PERCENT);
+    }
+    if !(MIN_TIME_LIMIT..=MAX_TIME_LIMIT).contains(&config.time_limit) {
+        warnings.push(format!(
+            "`time_limit` must be between {} and {}, got {}",
+            MIN_TIME_LIMIT, MAX_TIME_LIMIT, config.time_limit,
+        ));
+        config.time_limit = config.time_limit.clamp(MIN_TIME_LIMIT, MAX_TIME_LIMIT);
+    }
+    if !(1..=MAX_MISTAKE_LIMIT).contains(&config.mistake_limit) {
+        warnings.push(format!(
+            "`mistake_limit` must be between 1 and {}, got {}",
+            MAX_MISTAKE_LIMIT, config.mistake_limit,
+        ));
+        config.mistake_limit = config.mistake_limit.clamp(1, MAX_MISTAKE_LIMIT);
+    }
+    if !(1..=MAX_MEMORY_PREVIEW).contains(&config.memory_preview) {
+        warnings.push(format!(
+            "`memory_preview` must be between 1 and {}, got {}",
+            MAX_MEMORY_PREVIEW, config.memory_preview,
+        ));
+        config.memory_preview = config.memory_preview.clamp(1, MAX_MEMORY_PREVIEW);
+    }
+    if config.countdown > MAX_COUNTDOWN {
+        warnings.push(format!("`countdown` must be at most {}, got {}", MAX_COUNTDOWN, config.countdown));
+        config.countdown = MAX_COUNTDOWN;
+    }
+    if config.incorrect_penalty > MAX_INCORRECT_PENALTY {
+        warnings.push(format!(
+            "`incorrect_penalty` must be at most {}, got {}",
+            MAX_INCORRECT_PENALTY, config.incorrect_penalty,
+        ));
+        config.incorrect_penalty = MAX_INCORRECT_PENALTY;
+    }
+    // Tiles with the same or a blank label could not be told apart
+    let mut symbols: Vec<String> = Vec::new();
+    for symbol in std::mem::take(&mut config.custom_symbols) {
+        if symbol.trim().is_empty() {
+            warnings.push("Empty symbol in `custom_symbols` ignored".to_string());
+        } else if symbols.contains(&symbol) {
+            warnings.push(format!("Duplicate symbol `{}` in `custom_symbols` ignored", symbol));
+        } else {
+            symbols.push(symbol);
+        }
+    }
+    config.custom_symbols = symbols;
+    let defaults = GameConfiguraiton::default();
+    for (name, value, min, max, default) in [
+        ("gaze_radius", &mut config.gaze_radius, MIN_GAZE_RADIUS, MAX_GAZE_RADIUS, defaults.gaze_radius),
+        ("shuffle_animation", &mut config.shuffle_animation, 0.0, MAX_SHUFFLE_ANIMATION, defaults.shuffle_animation),
+        ("timer_duration", &mut config.timer_duration, 0.0, MAX_TIMER_DURATION, defaults.timer_duration),
+        ("game_over_auto_return", &mut config.game_over_auto_return, 0.0, MAX_AUTO_RETURN, defaults.game_over_auto_return),
+        ("button_size", &mut config.button_size, MIN_BUTTON_SIZE, MAX_BUTTON_SIZE, defaults.button_size),
+        ("button_padding", &mut config.button_padding, 0.0, MAX_BUTTON_PADDING, defaults.button_padding),
+        ("font_size", &mut config.font_size, MIN_FONT_SIZE, MAX_FONT_SIZE, defaults.font_size),
+    ] {
+        // Clamping keeps NaN, and timers panic on anything that is not a finite duration
+        if !value.is_finite() {
+            warnings.push(format!("`{}` must be a finite number, got {}", name, value));
+            *value = default;
+        } else if !(min..=max).contains(value) {
+            warnings.push(format!("`{}` must be between {} and {}, got {}", name, min, max, value));
+            *value = value.clamp(min, max);
+        }
+    }
+}
+
+pub fn save_configuration(config: &GameConfiguraiton) -> Result<(), ConfigError> {
+    let proj_dirs = project_dirs().ok_or(ConfigError::NoProjectDirs)?;
+    let config_dir = proj_dirs.config_dir();
+    fs::create_dir_all(config_dir)?;
+    fs::write(config_dir.join(CONFIG_FILE_NAME), toml::to_string_pretty(config)?)?;
+    Ok(())
+}
+
+// Serializes colors as sRGB hex strings so they stay editable by hand
+pub mod hex_color {
+    use bevy::color::{Color, Srgba};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&color.to_srgba().to_hex())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        Srgba::hex(&hex)
+            .map(Color::from)
+            .map_err(|error| D::Error::custom(format!("invalid color `{}`: {}", hex, error)))
+    }
+}
+
+// Serializes seeds as decimal strings, TOML integers cannot hold every `u64`, an empty string means random
+pub mod seed {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(seed: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error> {
+        match seed {
+            Some(seed) => serializer.serialize_str(&seed.to_string()),
+            None => serializer.serialize_str(""),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+        let seed = String::deserialize(deserializer)?;
+        if seed.trim().is_empty() {
+            return Ok(None);
+        }
+        seed.trim().parse()
+            .map(Some)
+            .map_err(|error| D::Error::custom(format!("invalid seed `{}`: {}", seed, error)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(contents: &str) -> (GameConfiguraiton, ConfigWarnings) {
+        let mut warnings = ConfigWarnings::default();
+        let config = parse_configuration(contents, &mut warnings);
+        (config, warnings)
+    }
+
+    #[test]
+    fn valid_settings_are_loaded_without_warnings() {
+        let (config, warnings) = parse("width = 7\ntimer_duration = 0.5\ncountdown = 0");
+        assert!(warnings.is_empty());
+        assert_eq!(config.width, 7);
+        assert_eq!(config.timer_duration, 0.5);
+        assert_eq!(config.countdown, 0);
+    }
+
+    #[test]
+    fn unknown_keys_are_ignored_with_a_warning() {
+        let (config, warnings) = parse("colour = \"red\"\nheight = 4");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("`colour`"));
+        assert_eq!(config.height, 4);
+    }
+
+    #[test]
+    fn values_of_the_wrong_type_keep_the_default() {
+        let (config, warnings) = parse("width = \"wide\"\nheight = 4");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("`width`"));
+        assert_eq!(config.width, GameConfiguraiton::default().width);
+        assert_eq!(config.height, 4);
+    }
+
+    #[test]
+    fn malformed_files_use_the_default_settings() {
+        let (config, warnings) = parse("width = ");
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(config.width, GameConfiguraiton::default().width);
+    }
+
+    #[test]
+    fn values_out_of_range_are_clamped() {
+        let (config, warnings) = parse(
+            "width = 50\ncountdown = 50\nincorrect_penalty = 200\ntimer_duration = -1.0\ngame_over_auto_return = 1e30",
+        );
+        assert_eq!(warnings.len(), 5);
+        assert_eq!(config.width, MAX_GRID_SIZE);
+        assert_eq!(config.countdown, MAX_COUNTDOWN);
+        assert_eq!(config.incorrect_penalty, MAX_INCORRECT_PENALTY);
+        assert_eq!(config.timer_duration, 0.0);
+        assert_eq!(config.game_over_auto_return, MAX_AUTO_RETURN);
+    }
+
+    #[test]
+    fn empty_and_duplicate_custom_symbols_are_dropped() {
+        let (config, warnings) = parse("custom_symbols = [\"★\", \"\", \"☆\", \"★\", \" \"]");
+        assert_eq!(warnings.len(), 3);
+        assert_eq!(config.custom_symbols, ["★", "☆"]);
+    }
+
+    #[test]
+    fn non_finite_floats_fall_back_to_the_default() {
+        let (config, warnings) = parse("timer_duration = nan\ngame_over_auto_return = inf\ngaze_radius = -inf");
+        let defaults = GameConfiguraiton::default();
+        assert_eq!(warnings.len(), 3);
+        assert_eq!(config.timer_duration, defaults.timer_duration);
+        assert_eq!(config.game_over_auto_return, defaults.game_over_auto_return);
+        assert_eq!(config.gaze_radius, defaults.gaze_radius);
+    }
+}
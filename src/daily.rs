@@ -0,0 +1,218 @@
+use bevy::prelude::*;
+use chrono::{Datelike, Local, NaiveDate};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use super::score_store::{ScoreRecord, ScoreStore};
+use super::{
+    GameState,
+    GameConfiguraiton,
+    GameMode,
+};
+
+pub fn daily_plugin(app: &mut App) {
+    app.add_systems(
+        OnEnter(GameState::Menu),
+        end_daily_challenge.run_if(resource_exists::<DailyChallenge>),
+    );
+}
+
+// Today's challenge while it is being played, the configuration of the player is put back in the menu
+#[derive(Resource)]
+pub struct DailyChallenge {
+    pub date: NaiveDate,
+    // Only the first attempt of the day is official, the following ones are practice
+    official: bool,
+    last_official: bool,
+    player_config: GameConfiguraiton,
+}
+
+impl DailyChallenge {
+    // Replaces `config` with the rules of today's challenge
+    pub fn start(config: &mut GameConfiguraiton) -> Self {
+        let date = today();
+        // Without the score file there is no way to tell, so the attempt stays official
+        let official = match ScoreStore::from_config(config).and_then(|store| store.load()) {
+            Ok(records) => !records.iter().any(|record| record.daily == Some(date) && !record.practice),
+            Err(error) => {
+                warn!("Unable to check earlier daily challenges: {}", error);
+                true
+            }
+        };
+        let player_config = std::mem::replace(config, configuration(date, config));
+        DailyChallenge {
+            date,
+            official,
+            last_official: false,
+            player_config,
+        }
+    }
+
+    // Tags a finished or abandoned attempt, which uses up the official attempt
+    pub fn mark(&mut self, record: &mut ScoreRecord) {
+        record.daily = Some(self.date);
+        record.practice = !self.official;
+        self.last_official = self.official;
+        self.official = false;
+    }
+
+    pub fn describe_last(&self) -> String {
+        if self.last_official {
+            format!("Daily challenge {}", self.date)
+        } else {
+            format!("Daily challenge {} (practice)", self.date)
+        }
+    }
+}
+
+fn end_daily_challenge(
+    mut commands: Commands,
+    daily: Res<DailyChallenge>,
+    mut config: ResMut<GameConfiguraiton>,
+) {
+    *config = daily.player_config.clone();
+    commands.remove_resource::<DailyChallenge>();
+}
+
+pub fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+// Spreads consecutive days over unrelated seeds (splitmix64), the same on every machine
+pub fn seed(date: NaiveDate) -> u64 {
+    let mut z = (date.num_days_from_ce() as u64).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+// Rules of the day, everything that only changes the look of the game is kept from `base`
+pub fn configuration(date: NaiveDate, base: &GameConfiguraiton) -> GameConfiguraiton {
+    let seed = seed(date);
+    // The same named generator as the tables, so every machine draws the same rules
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let rules = GameConfiguraiton::default();
+    let size = rng.gen_range(4..=7);
+    GameConfiguraiton {
+        mode: *GameMode::ALL.choose(&mut rng).expect("There are game modes"),
+        sequence_start: rules.sequence_start,
+        sequence_step: rules.sequence_step,
+        sequence_descending: rng.gen_bool(0.25),
+        symbols: rules.symbols,
+        width: size,
+        height: size,
+        distractors: rules.distractors,
+        distractor_kind: rules.distractor_kind,
+        goal: rules.goal,
+        time_limit: rules.time_limit,
+        mistake_limit: rules.mistake_limit,
+        shuffle: rules.shuffle,
+        fixation: rules.fixation,
+        perturb_rotation: rng.gen_range(0..=3),
+        perturb_size: rules.perturb_size,
+        perturb_color: rules.perturb_color,
+        perturb_mirror: rules.perturb_mirror,
+        seed: Some(seed),
+        penalty_policy: rules.penalty_policy,
+        incorrect_penalty: rules.incorrect_penalty,
+        penalty_percent: rules.penalty_percent,
+        memory_preview: rules.memory_preview,
+        start_timer_on_first_click: rules.start_timer_on_first_click,
+        // Showing the next number changes the exercise, and everyone gets the same countdown
+        hud_next_number: rules.hud_next_number,
+        countdown: rules.countdown,
+        ..base.clone()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Streaks {
+    // Consecutive days up to today, or yesterday while today's challenge is still open
+    pub current: u32,
+    pub best: u32,
+}
+
+pub fn streaks(dates: impl Iterator<Item = NaiveDate>, today: NaiveDate) -> Streaks {
+    let mut dates: Vec<NaiveDate> = dates.collect();
+    dates.sort_unstable();
+    dates.dedup();
+    let mut streaks = Streaks::default();
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+    for date in dates {
+        run = if previous.and_then(|previous| previous.succ_opt()) == Some(date) { run + 1 } else { 1 };
+        streaks.best = streaks.best.max(run);
+        previous = Some(date);
+    }
+    if previous == Some(today) || previous == today.pred_opt() {
+        streaks.current = run;
+    }
+    streaks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    fn streaks_of(days: &[u32], today: u32) -> Streaks {
+        streaks(days.iter().map(|day| date(*day)), date(today))
+    }
+
+    #[test]
+    fn seeds_are_stable_and_differ_between_days() {
+        assert_eq!(seed(date(1)), seed(date(1)));
+        assert_ne!(seed(date(1)), seed(date(2)));
+    }
+
+    #[test]
+    fn configuration_is_pinned() {
+        let config = configuration(date(1), &GameConfiguraiton::default());
+        // Players compare the challenge of a day across machines and versions
+        assert_eq!(config.seed, Some(11_446_773_249_474_946_846));
+        assert_eq!(config.mode, GameMode::Classic);
+        assert_eq!((config.width, config.height), (5, 5));
+        assert!(!config.sequence_descending);
+        assert_eq!(config.perturb_rotation, 2);
+    }
+
+    #[test]
+    fn no_dates_means_no_streak() {
+        let streaks = streaks_of(&[], 10);
+        assert_eq!((streaks.current, streaks.best), (0, 0));
+    }
+
+    #[test]
+    fn a_gap_ends_the_streak() {
+        let streaks = streaks_of(&[1, 2, 3, 5, 6], 6);
+        assert_eq!((streaks.current, streaks.best), (2, 3));
+    }
+
+    #[test]
+    fn a_streak_ending_yesterday_is_still_current() {
+        let streaks = streaks_of(&[7, 8, 9], 10);
+        assert_eq!((streaks.current, streaks.best), (3, 3));
+    }
+
+    #[test]
+    fn a_streak_ending_before_yesterday_is_over() {
+        let streaks = streaks_of(&[7, 8], 10);
+        assert_eq!((streaks.current, streaks.best), (0, 2));
+    }
+
+    #[test]
+    fn duplicate_days_count_once() {
+        let streaks = streaks_of(&[9, 8, 9, 10, 10], 10);
+        assert_eq!((streaks.current, streaks.best), (3, 3));
+    }
+
+    #[test]
+    fn streaks_continue_across_months() {
+        let dates = [NaiveDate::from_ymd_opt(2024, 2, 29).unwrap(), date(1)];
+        let streaks = streaks(dates.into_iter(), date(1));
+        assert_eq!((streaks.current, streaks.best), (2, 2));
+    }
+}
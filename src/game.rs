@@ -1,25 +1,96 @@
 use bevy::{
     prelude::*,
     time::Stopwatch,
+    ecs::system::SystemParam,
+    window::WindowFocused,
 };
-use rand::{
-    seq::SliceRandom,
-    thread_rng,
-};
+use std::{f32::consts::FRAC_PI_2, time::Duration};
+use rand::{thread_rng, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
 use super::{
+    board::{ClickOutcome, SchulteBoard, Target, TileColor},
+    daily::DailyChallenge,
     despawn_screen,
+    ghost::Ghost,
+    menu::NORMAL_BUTTON,
+    replay::RunRecording,
+    score_store::{ScoreRecord, ScoreStore},
+    FixationMode,
+    GameMode,
+    GamePhase,
+    RunGoal,
     GameState,
     GameConfiguraiton,
+    MAX_PERTURBATION,
 };
 
 pub fn game_plugin(app: &mut App) {
     app
-        .init_resource::<NextExpected>()
-        .init_resource::<Penalty>()
-        .add_systems(OnEnter(GameState::Game), game_setup)
-        .add_systems(Update, (click_handler, blink_system, check_game_over, update_timer)
-            .run_if(in_state(GameState::Game)))
+        .init_resource::<RunLog>()
+        .init_resource::<ReplaySeed>()
+        .add_event::<RestartGame>()
+        .add_event::<TileFound>()
+        .add_event::<TableCompleted>()
+        .add_event::<SequenceRestarted>()
+        .add_systems(OnEnter(GameState::Game), (reset_run, game_setup).chain())
+        .add_systems(OnEnter(GamePhase::Countdown), countdown_setup)
+        .add_systems(Update, countdown.run_if(in_state(GamePhase::Countdown)))
+        .add_systems(OnExit(GamePhase::Countdown), despawn_screen::<OnCountdownScreen>)
+        .add_systems(OnEnter(GamePhase::Preview), preview_setup)
+        .add_systems(Update, preview.run_if(in_state(GamePhase::Preview)))
+        .add_systems(OnExit(GamePhase::Preview), despawn_screen::<OnPreviewScreen>)
+        .add_systems(OnEnter(GamePhase::Playing), (reveal_tiles, start_stopwatch))
+        .add_systems(OnEnter(GamePhase::Paused), pause_setup)
+        .add_systems(OnExit(GamePhase::Paused), despawn_screen::<OnPauseScreen>)
+        .add_systems(
+            Update,
+            (
+                blink_system,
+                check_game_over.after(click_handler),
+                game_button_action,
+                pause_shortcuts,
+                update_hud,
+            )
+                .run_if(in_state(GameState::Game)),
+        )
+        .add_systems(
+            Update,
+            fixation_styling.run_if(in_state(GameState::Game).and_then(fixation_enabled)),
+        )
+        .add_systems(Update, (click_handler, update_timer, pause_on_focus_loss)
+            .run_if(in_state(GamePhase::Playing)))
+        .add_systems(
+            Update,
+            (
+                reshuffle_tiles
+                    .after(click_handler)
+                    .run_if(on_event::<TileFound>()),
+                move_tiles,
+                // Memory mode keeps every label hidden until its tile is found
+                reveal_tiles
+                    .after(click_handler)
+                    .run_if(on_event::<TileFound>().or_else(on_event::<SequenceRestarted>())),
+            )
+                .run_if(in_state(GameState::Game)),
+        )
+        // Restarting stays in `GameState::Game`, so the screen is rebuilt without `OnEnter`
+        .add_systems(
+            Update,
+            (record_abandoned_run, reset_run, despawn_screen::<OnGameScreen>, game_setup)
+                .chain()
+                .after(game_button_action)
+                .run_if(on_event::<RestartGame>()),
+        )
+        // Runs that continue past a finished table replace it while the clock keeps going
+        .add_systems(
+            Update,
+            (advance_table, despawn_screen::<OnGameScreen>, game_setup, reveal_tiles, next_table_phase)
+                .chain()
+                .after(check_game_over)
+                .after(reshuffle_tiles)
+                .run_if(in_state(GameState::Game).and_then(on_event::<TableCompleted>())),
+        )
         .add_systems(OnExit(GameState::Game), despawn_screen::<OnGameScreen>);
 }
 
@@ -27,44 +98,215 @@ pub fn game_plugin(app: &mut App) {
 struct OnGameScreen;
 
 #[derive(Component)]
-struct TileButton {
-    number: u8,
+struct OnCountdownScreen;
+
+#[derive(Component)]
+struct OnPauseScreen;
+
+#[derive(Component)]
+struct OnPreviewScreen;
+
+// All actions that can be triggered from the buttons shown during a game
+#[derive(Component, Clone, Copy)]
+enum GameButtonAction {
+    Pause,
+    Resume,
+    Restart,
+    Quit,
+}
+
+// Optional information displayed while playing
+#[derive(Component, Clone, Copy, Debug)]
+pub enum HudElement {
+    Timer,
+    NextNumber,
+    Progress,
+    Mistakes,
+}
+
+impl HudElement {
+    pub fn enabled(&self, config: &GameConfiguraiton) -> bool {
+        match self {
+            HudElement::Timer => config.hud_timer,
+            HudElement::NextNumber => config.hud_next_number,
+            HudElement::Progress => config.hud_progress,
+            HudElement::Mistakes => config.hud_mistakes,
+        }
+    }
+
+    pub fn toggle(&self, config: &mut GameConfiguraiton) {
+        let enabled = match self {
+            HudElement::Timer => &mut config.hud_timer,
+            HudElement::NextNumber => &mut config.hud_next_number,
+            HudElement::Progress => &mut config.hud_progress,
+            HudElement::Mistakes => &mut config.hud_mistakes,
+        };
+        *enabled = !*enabled;
+    }
+}
+
+// Sent to throw away the current run and set up a new table with the same settings
+#[derive(Event)]
+struct RestartGame;
+
+// Text of a tile, hidden until the game starts, with the size and color it was given at setup
+#[derive(Component)]
+struct TileLabel {
+    font_size: f32,
+    color: Color,
 }
 
 #[derive(Component)]
-struct TileBlink {
+struct CountdownText;
+
+#[derive(Resource, Deref, DerefMut)]
+struct CountdownTimer(Timer);
+
+#[derive(Component)]
+struct PreviewText;
+
+#[derive(Resource, Deref, DerefMut)]
+struct PreviewTimer(Timer);
+
+// Node containing the tiles, its center is the fixation point
+#[derive(Component)]
+pub struct TileGrid;
+
+#[derive(Component)]
+struct TileButton;
+
+// Cell of the board a tile currently occupies, counted row by row
+#[derive(Component, Deref, DerefMut)]
+pub struct TileSlot(usize);
+
+// Animation of a tile towards a new slot after a reshuffle
+#[derive(Component)]
+pub struct TileMove {
+    from: Vec2,
+    to: Vec2,
     timer: Timer,
 }
 
+// Sent for every correct click, after the board has advanced
+#[derive(Event)]
+struct TileFound;
+
+// Sent when a mistake sends the player back to the first number
+#[derive(Event)]
+struct SequenceRestarted;
+
+// Sent when a table is finished but the run goes on with a new one
+#[derive(Event)]
+struct TableCompleted;
+
+#[derive(Component)]
+pub struct TileBlink {
+    timer: Timer,
+}
+
+// Rules and progress of the current run, the display follows it
 #[derive(Resource, Deref, DerefMut)]
-struct NextExpected(u8);
+pub struct Board(SchulteBoard);
 
-impl Default for NextExpected {
-    fn default() -> Self {
-        NextExpected(1)
+// Randomness of the label perturbations, apart from the board so recorded runs can be simulated without the display
+#[derive(Resource, Deref, DerefMut)]
+pub struct LabelRng(ChaCha8Rng);
+
+impl LabelRng {
+    pub fn new(seed: u64) -> Self {
+        // Keeps the labels from repeating the draws of the board shuffle
+        LabelRng(ChaCha8Rng::seed_from_u64(seed ^ 0x5DEE_CE66_D1CE_4E5B))
     }
 }
 
+// Seed of the next game in place of the configured one, set to replay a table
 #[derive(Resource, Default, Deref, DerefMut)]
-pub struct Penalty(u8);
+pub struct ReplaySeed(pub Option<u64>);
+
+// A single click on a tile, timestamped against the game stopwatch
+pub struct RunClick {
+    pub time: Duration,
+    // None when a distractor was clicked
+    pub target: Option<Target>,
+    pub correct: bool,
+}
+
+// Every click of the current run in order
+#[derive(Resource, Default)]
+pub struct RunLog {
+    pub clicks: Vec<RunClick>,
+}
+
+impl RunLog {
+    // Time needed to find each number since the previous one was found
+    pub fn splits(&self) -> Vec<(Target, Duration)> {
+        let mut previous = Duration::ZERO;
+        self.clicks.iter()
+            .filter(|click| click.correct)
+            .filter_map(|click| {
+                let split = click.time - previous;
+                previous = click.time;
+                Some((click.target?, split))
+            })
+            .collect()
+    }
+
+    // Time between each wrong click and the following correct one
+    pub fn recovery_time(&self) -> Duration {
+        let mut total = Duration::ZERO;
+        let mut first_mistake = None;
+        for click in &self.clicks {
+            match (click.correct, first_mistake) {
+                (false, None) => first_mistake = Some(click.time),
+                (true, Some(time)) => {
+                    total += click.time - time;
+                    first_mistake = None;
+                }
+                _ => {}
+            }
+        }
+        total
+    }
+}
 
 #[derive(Resource, Deref, DerefMut)]
 pub struct GameDuration {
     pub time: Stopwatch
 }
 
+// Clears everything accumulated over a run, before its first table is set up
+fn reset_run(
+    mut commands: Commands,
+    mut run_log: ResMut<RunLog>,
+    mut replay_seed: ResMut<ReplaySeed>,
+    config: Res<GameConfiguraiton>,
+) {
+    // A replay only applies to the next game, restarting it deals a new table
+    let seed = replay_seed.take()
+        .or(config.seed)
+        .unwrap_or_else(|| thread_rng().gen());
+    commands.insert_resource(Board(SchulteBoard::new(config.board_rules(), seed)));
+    commands.insert_resource(LabelRng::new(seed));
+    commands.insert_resource(RunRecording::new(seed, &config));
+    match config.ghost.then(|| Ghost::personal_best(&config, seed)).flatten() {
+        Some(ghost) => commands.insert_resource(ghost),
+        None => commands.remove_resource::<Ghost>(),
+    }
+    *run_log = RunLog::default();
+    // The stopwatch only starts once the numbers are revealed
+    let mut time = Stopwatch::new();
+    time.pause();
+    commands.insert_resource(GameDuration { time });
+}
+
 fn game_setup(
     mut commands: Commands,
-    mut next_expected: ResMut<NextExpected>,
-    mut penalty: ResMut<Penalty>,
+    board: Res<Board>,
+    mut label_rng: ResMut<LabelRng>,
+    asset_server: Res<AssetServer>,
     config: Res<GameConfiguraiton>,
 ) {
-    *next_expected = NextExpected::default();
-    *penalty = Penalty::default();
-    // Generate random numbers for the grid
-    let mut numbers: Vec<u8> = (1u8..=(config.width * config.height) as u8).collect();
-    let mut rng = thread_rng();
-    numbers.shuffle(&mut rng);
+    let symbol_font = asset_server.load(&config.symbol_font);
 
     // Root UI node
     commands
@@ -83,85 +325,675 @@ fn game_setup(
                 OnGameScreen,
         ))
         .with_children(|parent| {
-            // Create a grid of buttons for the Schulte table
-            parent.
-                spawn(
-                    NodeBundle {
+            spawn_table(parent, &board, &mut label_rng, &config, &symbol_font, Visibility::Hidden);
+            // HUD with only the elements enabled in the configuration
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        top: Val::Px(20.0),
+                        left: Val::Px(20.0),
+                        flex_direction: FlexDirection::Column,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|hud| {
+                    for element in [
+                        HudElement::Timer,
+                        HudElement::NextNumber,
+                        HudElement::Progress,
+                        HudElement::Mistakes,
+                    ] {
+                        if element.enabled(&config) {
+                            // The next number is written with the symbols of the table
+                            let font = match element {
+                                HudElement::NextNumber => symbol_font.clone(),
+                                _ => Handle::default(),
+                            };
+                            hud.spawn((
+                                TextBundle::from_section(
+                                    "",
+                                    TextStyle {
+                                        font,
+                                        font_size: 25.0,
+                                        color: config.color_text,
+                                    },
+                                ),
+                                element,
+                            ));
+                        }
+                    }
+                });
+            parent
+                .spawn((
+                    ButtonBundle {
                         style: Style {
-                            height: Val::Px((config.button_size + config.button_padding * 2.0) * config.height as f32), 
-                            width: Val::Px((config.button_size + config.button_padding * 2.0) * config.width as f32),
-                            flex_wrap: FlexWrap::Wrap,
-                            justify_content: JustifyContent::SpaceAround,
-                            align_items: AlignItems::Center,
+                            position_type: PositionType::Absolute,
+                            top: Val::Px(20.0),
+                            right: Val::Px(20.0),
+                            padding: UiRect::all(Val::Px(10.0)),
                             ..default()
                         },
+                        background_color: NORMAL_BUTTON.into(),
                         ..default()
                     },
-                )
-                .with_children(|grid| {
-                    for &number in numbers.iter() {
-                        grid.spawn(ButtonBundle {
-                            style: Style {
-                                height: Val::Px(config.button_size),
-                                width: Val::Px(config.button_size),
-                                margin: UiRect::all(Val::Px(config.button_padding)),
-                                justify_content: JustifyContent::Center,
-                                align_items: AlignItems::Center,
-                                ..default()
-                            },
-                            background_color: BackgroundColor(config.color_default).into(),
+                    GameButtonAction::Pause,
+                ))
+                .with_children(|button| {
+                    button.spawn(TextBundle::from_section(
+                        "Pause (Esc)",
+                        TextStyle {
+                            font_size: 25.0,
+                            color: config.color_text,
                             ..default()
-                        })
-                        .insert(TileButton { number })
-                            .with_children(|button| {
-                                button.spawn(TextBundle {
-                                    text: Text::from_section(
-                                              number.to_string(),
-                                              TextStyle {
-                                                  font_size: config.font_size,
-                                                  color: config.color_text,
-                                                  ..default()
-                                              },
-                                          ),
-                                          ..default()
-                                });
-                            });
-                    }
+                        },
+                    ));
                 });
         });
-    commands.insert_resource(GameDuration {
-        time: Stopwatch::new(),
-    });
+}
+
+// Grid of buttons for the Schulte table, its labels are shown with `label_visibility`
+pub fn spawn_table(
+    parent: &mut ChildBuilder,
+    board: &SchulteBoard,
+    label_rng: &mut LabelRng,
+    config: &GameConfiguraiton,
+    symbol_font: &Handle<Font>,
+    label_visibility: Visibility,
+) -> Entity {
+    parent
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    height: Val::Px((config.button_size + config.button_padding * 2.0) * config.height as f32),
+                    width: Val::Px((config.button_size + config.button_padding * 2.0) * config.width as f32),
+                    ..default()
+                },
+                ..default()
+            },
+            TileGrid,
+        ))
+        .with_children(|grid| {
+            // Tiles are positioned absolutely by slot so they can move when reshuffled
+            for (slot, tile) in board.cells().iter().enumerate() {
+                let label = &tile.label;
+                let (transform, tile_label) =
+                    perturbed_label(label_color(tile.color, config), config, &mut **label_rng);
+                let position = slot_position(slot, config);
+                grid.spawn(ButtonBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(position.x),
+                        top: Val::Px(position.y),
+                        height: Val::Px(config.button_size),
+                        width: Val::Px(config.button_size),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: BackgroundColor(config.color_default),
+                    ..default()
+                })
+                .insert((TileButton, TileSlot(slot)))
+                .with_children(|button| {
+                    button.spawn((
+                        TextBundle {
+                            text: Text::from_section(
+                                label.clone(),
+                                TextStyle {
+                                    font: label_font(label, symbol_font),
+                                    font_size: tile_label.font_size,
+                                    color: tile_label.color,
+                                },
+                            ),
+                            transform,
+                            visibility: label_visibility,
+                            ..default()
+                        },
+                        tile_label,
+                    ));
+                });
+            }
+            if config.fixation != FixationMode::Off {
+                let grid_size = Vec2::new(config.width as f32, config.height as f32)
+                    * (config.button_size + config.button_padding * 2.0);
+                grid.spawn(NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(grid_size.x / 2.0 - FIXATION_MARKER_SIZE / 2.0),
+                        top: Val::Px(grid_size.y / 2.0 - FIXATION_MARKER_SIZE / 2.0),
+                        width: Val::Px(FIXATION_MARKER_SIZE),
+                        height: Val::Px(FIXATION_MARKER_SIZE),
+                        ..default()
+                    },
+                    background_color: config.color_fixation.into(),
+                    border_radius: BorderRadius::MAX,
+                    // Stays on top of the tiles that move through the center
+                    z_index: ZIndex::Local(1),
+                    ..default()
+                });
+            }
+        })
+        .id()
+}
+
+// The default font only covers ASCII, other alphabets fall back to the symbol font
+fn label_font(label: &str, symbol_font: &Handle<Font>) -> Handle<Font> {
+    if label.is_ascii() {
+        Handle::default()
+    } else {
+        symbol_font.clone()
+    }
+}
+
+// Random rotation, size, color and mirroring of a label, all levels 0 leave it untouched.
+// The layout only sets the translation of UI nodes, so rotation and scale survive it.
+fn perturbed_label(color: Color, config: &GameConfiguraiton, rng: &mut impl Rng) -> (Transform, TileLabel) {
+    let level = |level: u8| level as f32 / MAX_PERTURBATION as f32;
+    let perturbation = config.perturbation();
+    // At most a quarter turn either way, upside down labels like 6 and 9 could not be told apart
+    let rotation = rng.gen_range(-1.0..=1.0) * level(perturbation.rotation) * FRAC_PI_2;
+    let size = 1.0 + rng.gen_range(-0.5..=0.5) * level(perturbation.size);
+    // Gorbov labels keep their color, it tells the two sequences apart
+    let color = if perturbation.color > 0 && config.mode != GameMode::Gorbov {
+        let hue = Color::hsl(rng.gen_range(0.0..360.0), 0.9, 0.5);
+        color.mix(&hue, 0.7 * level(perturbation.color))
+    } else {
+        color
+    };
+    let mirror = if perturbation.mirror && rng.gen() { -1.0 } else { 1.0 };
+    let transform = Transform::from_rotation(Quat::from_rotation_z(rotation))
+        .with_scale(Vec3::new(mirror, 1.0, 1.0));
+    (transform, TileLabel { font_size: config.font_size * size, color })
+}
+
+fn label_color(color: TileColor, config: &GameConfiguraiton) -> Color {
+    match (config.mode, color) {
+        (GameMode::Classic | GameMode::Memory, _) => config.color_text,
+        (GameMode::Gorbov, TileColor::Black) => config.color_gorbov_black,
+        (GameMode::Gorbov, TileColor::Red) => config.color_gorbov_red,
+    }
+}
+
+fn countdown_setup(
+    mut commands: Commands,
+    mut game_phase: ResMut<NextState<GamePhase>>,
+    config: Res<GameConfiguraiton>,
+) {
+    if config.countdown == 0 {
+        game_phase.set(phase_after_countdown(&config));
+        return;
+    }
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    height: Val::Percent(100.0),
+                    width: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                z_index: ZIndex::Global(1),
+                ..default()
+            },
+            OnCountdownScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    config.countdown.to_string(),
+                    TextStyle {
+                        font_size: 150.0,
+                        color: config.color_text,
+                        ..default()
+                    },
+                ),
+                CountdownText,
+            ));
+        });
+    commands.insert_resource(CountdownTimer(Timer::from_seconds(
+        config.countdown as f32,
+        TimerMode::Once,
+    )));
+}
+
+fn countdown(
+    time: Res<Time>,
+    mut timer: ResMut<CountdownTimer>,
+    mut text_query: Query<&mut Text, With<CountdownText>>,
+    mut game_phase: ResMut<NextState<GamePhase>>,
+    config: Res<GameConfiguraiton>,
+) {
+    if timer.tick(time.delta()).finished() {
+        game_phase.set(phase_after_countdown(&config));
+        return;
+    }
+    let remaining = timer.remaining_secs().ceil().to_string();
+    for mut text in &mut text_query {
+        text.sections[0].value.clone_from(&remaining);
+    }
+}
+
+// A new table of a memory run needs its own preview, otherwise play simply goes on
+fn next_table_phase(
+    mut game_phase: ResMut<NextState<GamePhase>>,
+    config: Res<GameConfiguraiton>,
+) {
+    if config.mode == GameMode::Memory {
+        game_phase.set(GamePhase::Preview);
+    }
+}
+
+fn phase_after_countdown(config: &GameConfiguraiton) -> GamePhase {
+    match config.mode {
+        GameMode::Memory => GamePhase::Preview,
+        _ => GamePhase::Playing,
+    }
+}
+
+fn preview_setup(
+    mut commands: Commands,
+    mut label_query: Query<&mut Visibility, With<TileLabel>>,
+    config: Res<GameConfiguraiton>,
+) {
+    for mut visibility in &mut label_query {
+        *visibility = Visibility::Inherited;
+    }
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(20.0),
+                    width: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            OnPreviewScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 40.0,
+                        color: config.color_text,
+                        ..default()
+                    },
+                ),
+                PreviewText,
+            ));
+        });
+    commands.insert_resource(PreviewTimer(Timer::from_seconds(
+        config.memory_preview as f32,
+        TimerMode::Once,
+    )));
+}
+
+fn preview(
+    time: Res<Time>,
+    mut timer: ResMut<PreviewTimer>,
+    mut text_query: Query<&mut Text, With<PreviewText>>,
+    mut game_phase: ResMut<NextState<GamePhase>>,
+) {
+    if timer.tick(time.delta()).finished() {
+        game_phase.set(GamePhase::Playing);
+        return;
+    }
+    let remaining = format!("Memorize: {}", timer.remaining_secs().ceil());
+    for mut text in &mut text_query {
+        text.sections[0].value.clone_from(&remaining);
+    }
+}
+
+fn pause_setup(
+    mut commands: Commands,
+    mut label_query: Query<&mut Visibility, With<TileLabel>>,
+    config: Res<GameConfiguraiton>,
+) {
+    // Hide the numbers so pausing cannot be used to scan the table
+    for mut visibility in &mut label_query {
+        *visibility = Visibility::Hidden;
+    }
+    let button_style = Style {
+        width: Val::Px(250.0),
+        height: Val::Px(65.0),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let button_text_style = TextStyle {
+        font_size: 33.0,
+        color: config.color_text,
+        ..default()
+    };
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    height: Val::Percent(100.0),
+                    width: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: config.color_background.into(),
+                z_index: ZIndex::Global(1),
+                ..default()
+            },
+            OnPauseScreen,
+        ))
+        .with_children(|parent| {
+            for (action, text) in [
+                (GameButtonAction::Resume, "Resume"),
+                (GameButtonAction::Restart, "Restart"),
+                (GameButtonAction::Quit, "Quit to menu"),
+            ] {
+                parent
+                    .spawn((
+                        ButtonBundle {
+                            style: button_style.clone(),
+                            background_color: NORMAL_BUTTON.into(),
+                            ..default()
+                        },
+                        action,
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(text, button_text_style.clone()));
+                    });
+            }
+        });
+}
+
+fn game_button_action(
+    interaction_query: Query<
+        (&Interaction, &GameButtonAction),
+        (Changed<Interaction>, With<Button>),
+    >,
+    current_phase: Res<State<GamePhase>>,
+    mut game_phase: ResMut<NextState<GamePhase>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut restart_events: EventWriter<RestartGame>,
+    mut abandoned_runs: AbandonedRuns,
+) {
+    for (interaction, action) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match action {
+            // Like the keyboard shortcuts, the countdown and memory preview cannot be paused
+            GameButtonAction::Pause if *current_phase.get() != GamePhase::Playing => {}
+            GameButtonAction::Pause => game_phase.set(GamePhase::Paused),
+            GameButtonAction::Resume => game_phase.set(GamePhase::Playing),
+            GameButtonAction::Restart => {
+                restart_events.send(RestartGame);
+                game_phase.set(GamePhase::Countdown);
+            }
+            GameButtonAction::Quit => {
+                abandoned_runs.record();
+                game_state.set(GameState::Menu);
+            }
+        }
+    }
+}
+
+fn pause_shortcuts(
+    keys: Res<ButtonInput<KeyCode>>,
+    current_phase: Res<State<GamePhase>>,
+    mut game_phase: ResMut<NextState<GamePhase>>,
+) {
+    if !keys.any_just_pressed([KeyCode::Escape, KeyCode::KeyP]) {
+        return;
+    }
+    match current_phase.get() {
+        GamePhase::Playing => game_phase.set(GamePhase::Paused),
+        GamePhase::Paused => game_phase.set(GamePhase::Playing),
+        GamePhase::Countdown | GamePhase::Preview => {}
+    }
+}
+
+fn pause_on_focus_loss(
+    mut focus_events: EventReader<WindowFocused>,
+    mut game_phase: ResMut<NextState<GamePhase>>,
+) {
+    if focus_events.read().any(|event| !event.focused) {
+        game_phase.set(GamePhase::Paused);
+    }
+}
+
+// Everything needed to store the current run as abandoned
+#[derive(SystemParam)]
+struct AbandonedRuns<'w> {
+    board: Res<'w, Board>,
+    game_duration: Res<'w, GameDuration>,
+    config: Res<'w, GameConfiguraiton>,
+    daily: Option<ResMut<'w, DailyChallenge>>,
+}
+
+impl AbandonedRuns<'_> {
+    fn record(&mut self) {
+        let elapsed = self.game_duration.time.elapsed();
+        let mut record = ScoreRecord::from_run(
+            &self.config,
+            elapsed.as_secs_f32(),
+            self.board.penalty().as_secs_f32(),
+            self.board.mistakes(),
+            self.board.score(elapsed),
+            self.board.seed(),
+        );
+        record.abandoned = true;
+        if let Some(daily) = self.daily.as_mut() {
+            daily.mark(&mut record);
+        }
+        if let Err(error) = ScoreStore::from_config(&self.config).and_then(|store| store.append(&record)) {
+            warn!("Unable to record abandoned run: {}", error);
+        }
+    }
+}
+
+fn record_abandoned_run(mut abandoned_runs: AbandonedRuns) {
+    abandoned_runs.record();
+}
+
+// Shows the labels, in memory mode only those of the tiles already found
+fn reveal_tiles(
+    tile_query: Query<(&TileSlot, &Children)>,
+    mut label_query: Query<&mut Visibility, With<TileLabel>>,
+    board: Res<Board>,
+    config: Res<GameConfiguraiton>,
+) {
+    for (slot, children) in &tile_query {
+        let shown = config.mode != GameMode::Memory || board.is_found(**slot);
+        let mut labels = label_query.iter_many_mut(children);
+        while let Some(mut visibility) = labels.fetch_next() {
+            *visibility = if shown { Visibility::Inherited } else { Visibility::Hidden };
+        }
+    }
+}
+
+fn start_stopwatch(
+    mut game_duration: ResMut<GameDuration>,
+    config: Res<GameConfiguraiton>,
+) {
+    if !config.start_timer_on_first_click {
+        game_duration.time.unpause();
+    }
 }
 
 fn click_handler(
     mut commands: Commands,
-    mut interaction_query: Query<(&Interaction, &mut BackgroundColor, Entity, &TileButton), (Changed<Interaction>, With<Button>)>,
-    mut next_expected: ResMut<NextExpected>,
-    mut penalty: ResMut<Penalty>,
+    mut interaction_query: Query<(&Interaction, &mut BackgroundColor, Entity, &TileSlot), (Changed<Interaction>, With<Button>)>,
+    mut board: ResMut<Board>,
+    mut run_log: ResMut<RunLog>,
+    mut recording: ResMut<RunRecording>,
+    mut game_duration: ResMut<GameDuration>,
+    mut found_events: EventWriter<TileFound>,
+    mut restart_events: EventWriter<SequenceRestarted>,
     config: Res<GameConfiguraiton>,
 ) {
-    for (interaction, mut color, entity, tile_button) in interaction_query.iter_mut() {
-        match *interaction {
-            Interaction::Pressed => {
-                let new_color = if tile_button.number == **next_expected {
-                    **next_expected += 1;
-                    config.color_correct
-                } else {
-                    **penalty += config.incorrect_penalty;
-                    config.color_incorrect
-                };
-                *color = BackgroundColor(new_color.into());
-                commands.entity(entity).insert(TileBlink {
-                    timer: Timer::from_seconds(config.timer_duration, TimerMode::Once),
-                });
+    for (interaction, mut color, entity, slot) in interaction_query.iter_mut() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let time = game_duration.time.elapsed();
+        recording.click(**slot, time);
+        let target = board.cells()[**slot].target;
+        let correct = match board.click(**slot, time) {
+            ClickOutcome::Correct(_) => {
+                // Only still paused when the timer waits for the first correct click
+                game_duration.time.unpause();
+                found_events.send(TileFound);
+                true
+            }
+            ClickOutcome::Mistake { restarted, .. } => {
+                if restarted {
+                    restart_events.send(SequenceRestarted);
+                }
+                false
+            }
+            ClickOutcome::Ignored => continue,
+        };
+        run_log.clicks.push(RunClick { time, target, correct });
+        blink_tile(&mut commands, entity, &mut color, correct, &config);
+    }
+}
+
+// Colors a clicked tile until `blink_system` puts the default color back
+pub fn blink_tile(
+    commands: &mut Commands,
+    entity: Entity,
+    background: &mut BackgroundColor,
+    correct: bool,
+    config: &GameConfiguraiton,
+) {
+    *background = BackgroundColor(if correct { config.color_correct } else { config.color_incorrect });
+    commands.entity(entity).insert(TileBlink {
+        timer: Timer::from_seconds(config.timer_duration, TimerMode::Once),
+    });
+}
+
+const FIXATION_MARKER_SIZE: f32 = 12.0;
+// Size and opacity of the labels in the corners of the table in peripheral mode
+const PERIPHERAL_MIN_SCALE: f32 = 0.4;
+const PERIPHERAL_MIN_ALPHA: f32 = 0.3;
+
+fn fixation_enabled(config: Res<GameConfiguraiton>) -> bool {
+    config.fixation != FixationMode::Off
+}
+
+// Styles every label by its distance to the fixation point, or to the cursor in gaze mode.
+// Opacity is changed rather than `Visibility`, which belongs to the pause and memory rules.
+fn fixation_styling(
+    tile_query: Query<(&GlobalTransform, &Children), With<TileButton>>,
+    grid_query: Query<(&Node, &GlobalTransform), With<TileGrid>>,
+    mut label_query: Query<(&mut Text, &TileLabel)>,
+    window_query: Query<&Window>,
+    config: Res<GameConfiguraiton>,
+) {
+    let Ok((grid, grid_transform)) = grid_query.get_single() else {
+        return;
+    };
+    let center = grid_transform.translation().truncate();
+    // Distance from the center to the tiles in the corners
+    let max_distance = ((grid.size() - config.button_size - config.button_padding * 2.0) / 2.0)
+        .length()
+        .max(1.0);
+    let cursor = window_query.get_single().ok().and_then(Window::cursor_position);
+    for (transform, children) in &tile_query {
+        let position = transform.translation().truncate();
+        let (scale, alpha) = match config.fixation {
+            FixationMode::Off => (1.0, 1.0),
+            FixationMode::Peripheral => {
+                let t = (position.distance(center) / max_distance).min(1.0);
+                (
+                    1.0 + (PERIPHERAL_MIN_SCALE - 1.0) * t,
+                    1.0 + (PERIPHERAL_MIN_ALPHA - 1.0) * t,
+                )
+            }
+            FixationMode::Gaze => match cursor {
+                Some(cursor) if cursor.distance(position) <= config.gaze_radius => (1.0, 1.0),
+                _ => (1.0, 0.0),
             },
-            _ => {}
+        };
+        let mut labels = label_query.iter_many_mut(children);
+        while let Some((mut text, tile_label)) = labels.fetch_next() {
+            let section = &mut text.sections[0];
+            section.style.font_size = tile_label.font_size * scale;
+            section.style.color = tile_label.color.with_alpha(alpha);
+        }
+    }
+}
+
+// Top left corner of a slot within the grid
+fn slot_position(slot: usize, config: &GameConfiguraiton) -> Vec2 {
+    let cell = config.button_size + config.button_padding * 2.0;
+    Vec2::new(
+        (slot % config.width) as f32 * cell + config.button_padding,
+        (slot / config.width) as f32 * cell + config.button_padding,
+    )
+}
+
+// Dynamic table: after each correct click the tiles trade places so positions cannot be memorized
+fn reshuffle_tiles(
+    mut commands: Commands,
+    mut tile_query: Query<(Entity, &mut TileSlot, &Style)>,
+    mut board: ResMut<Board>,
+    config: Res<GameConfiguraiton>,
+) {
+    let moves = board.reshuffle();
+    animate_reshuffle(&mut commands, tile_query.iter_mut(), &moves, &config);
+}
+
+// Sends the tiles to their new slots, `moves` holds the new slot of the tile in each old slot
+pub fn animate_reshuffle<'a>(
+    commands: &mut Commands,
+    tiles: impl Iterator<Item = (Entity, Mut<'a, TileSlot>, &'a Style)>,
+    moves: &[usize],
+    config: &GameConfiguraiton,
+) {
+    for (entity, mut slot, style) in tiles {
+        let new_slot = moves[**slot];
+        if new_slot == **slot {
+            continue;
+        }
+        **slot = new_slot;
+        let from = match (style.left, style.top) {
+            (Val::Px(left), Val::Px(top)) => Vec2::new(left, top),
+            _ => slot_position(new_slot, config),
+        };
+        commands.entity(entity).insert(TileMove {
+            from,
+            to: slot_position(new_slot, config),
+            timer: Timer::from_seconds(config.shuffle_animation, TimerMode::Once),
+        });
+    }
+}
+
+pub fn move_tiles(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut TileMove, &mut Style)>,
+) {
+    for (entity, mut tile_move, mut style) in query.iter_mut() {
+        tile_move.timer.tick(time.delta());
+        let position = tile_move.from.lerp(tile_move.to, tile_move.timer.fraction());
+        style.left = Val::Px(position.x);
+        style.top = Val::Px(position.y);
+        if tile_move.timer.finished() {
+            commands.entity(entity).remove::<TileMove>();
         }
     }
 }
 
-fn blink_system(
+pub fn blink_system(
     time: Res<Time>,
     mut commands: Commands,
     mut query: Query<(Entity, &mut TileBlink, &mut BackgroundColor)>,
@@ -170,21 +1002,56 @@ fn blink_system(
     for (entity, mut blink, mut color) in query.iter_mut() {
         blink.timer.tick(time.delta());
         if blink.timer.finished() {
-            *color = BackgroundColor(config.color_default.into());
+            *color = BackgroundColor(config.color_default);
             commands.entity(entity).remove::<TileBlink>();
         }
     }
 }
 
 fn check_game_over(
-    next_expected: Res<NextExpected>,
+    board: Res<Board>,
     mut game_duration: ResMut<GameDuration>,
     mut game_state: ResMut<NextState<GameState>>,
-    config: Res<GameConfiguraiton>,
+    mut completed_events: EventWriter<TableCompleted>,
 ) {
-    if **next_expected as usize > config.width * config.height {
+    if board.is_over(game_duration.time.elapsed()) {
         game_duration.time.pause();
         game_state.set(GameState::GameOver);
+    } else if board.is_complete() {
+        completed_events.send(TableCompleted);
+    }
+}
+
+fn advance_table(mut board: ResMut<Board>) {
+    board.next_table();
+}
+
+fn update_hud(
+    mut hud_query: Query<(&mut Text, &HudElement)>,
+    board: Res<Board>,
+    game_duration: Res<GameDuration>,
+    config: Res<GameConfiguraiton>,
+) {
+    for (mut text, element) in &mut hud_query {
+        text.sections[0].value = match (element, config.goal) {
+            (HudElement::Timer, RunGoal::TimeAttack) => {
+                let time_left = board.time_left(game_duration.time.elapsed());
+                format!("Time left: {:.1} s", time_left.as_secs_f32())
+            }
+            (HudElement::Timer, _) => format!("Time: {:.1} s", game_duration.time.elapsed_secs()),
+            (HudElement::NextNumber, _) => match board.next_target() {
+                Some(target) => format!("Next: {}", config.describe(target)),
+                None => "Next: -".to_string(),
+            },
+            (HudElement::Progress, RunGoal::FinishTable) => {
+                format!("Found: {}/{}", board.progress(), board.targets().len())
+            }
+            (HudElement::Progress, _) => format!("Found: {}", board.found()),
+            (HudElement::Mistakes, RunGoal::Endless) => {
+                format!("Mistakes: {}/{}", board.mistakes(), config.mistake_limit)
+            }
+            (HudElement::Mistakes, _) => format!("Mistakes: {}", board.mistakes()),
+        };
     }
 }
 
@@ -194,3 +1061,117 @@ fn update_timer(
 ) {
     game_duration.time.tick(time.delta());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ShuffleMode;
+
+    fn target(number: u16) -> Target {
+        Target { number, color: TileColor::Black }
+    }
+
+    fn click(millis: u64, number: Option<u16>, correct: bool) -> RunClick {
+        RunClick {
+            time: Duration::from_millis(millis),
+            target: number.map(target),
+            correct,
+        }
+    }
+
+    #[test]
+    fn splits_measure_the_time_since_the_previous_number() {
+        let run_log = RunLog {
+            clicks: vec![
+                click(1200, Some(1), true),
+                click(1500, Some(3), false),
+                click(2000, Some(2), true),
+                click(3500, Some(3), true),
+            ],
+        };
+        assert_eq!(run_log.splits(), [
+            (target(1), Duration::from_millis(1200)),
+            (target(2), Duration::from_millis(800)),
+            (target(3), Duration::from_millis(1500)),
+        ]);
+    }
+
+    #[test]
+    fn recovery_time_runs_from_the_first_mistake_to_the_next_number() {
+        let run_log = RunLog {
+            clicks: vec![
+                click(1000, Some(1), true),
+                click(1500, Some(4), false),
+                click(1800, None, false),
+                click(2500, Some(2), true),
+                click(3000, Some(3), true),
+                click(3200, Some(5), false),
+                click(3600, Some(4), true),
+                // A mistake nothing was found after adds nothing
+                click(4000, Some(6), false),
+            ],
+        };
+        assert_eq!(run_log.recovery_time(), Duration::from_millis(1000 + 400));
+    }
+
+    #[test]
+    fn first_split_is_zero_when_the_timer_starts_on_the_first_number() {
+        // The stopwatch stays at zero until the first number is found, mistakes included
+        let run_log = RunLog {
+            clicks: vec![
+                click(0, Some(2), false),
+                click(0, Some(1), true),
+                click(700, Some(2), true),
+            ],
+        };
+        assert_eq!(run_log.splits(), [
+            (target(1), Duration::ZERO),
+            (target(2), Duration::from_millis(700)),
+        ]);
+        assert_eq!(run_log.recovery_time(), Duration::ZERO);
+    }
+
+    // Layouts of every table after each correct click, and the perturbation drawn for each label
+    fn perturbed_run(seed: u64, draw_labels: bool) -> (Vec<Vec<String>>, Vec<Transform>) {
+        let config = GameConfiguraiton {
+            width: 4,
+            height: 4,
+            shuffle: ShuffleMode::All,
+            perturb_rotation: MAX_PERTURBATION,
+            perturb_size: MAX_PERTURBATION,
+            perturb_color: MAX_PERTURBATION,
+            perturb_mirror: true,
+            ..default()
+        };
+        let mut board = SchulteBoard::new(config.board_rules(), seed);
+        let mut label_rng = LabelRng::new(seed);
+        let mut layouts = Vec::new();
+        let mut transforms = Vec::new();
+        // Labels are drawn when a table is spawned, the display may skip them entirely
+        if draw_labels {
+            for tile in board.cells() {
+                transforms.push(perturbed_label(label_color(tile.color, &config), &config, &mut *label_rng).0);
+            }
+        }
+        while let Some(next) = board.next_target() {
+            let cell = board.cells().iter().position(|tile| tile.target == Some(next)).unwrap();
+            board.click(cell, Duration::ZERO);
+            board.reshuffle();
+            layouts.push(board.cells().iter().map(|tile| tile.label.clone()).collect());
+        }
+        (layouts, transforms)
+    }
+
+    #[test]
+    fn perturbed_tables_are_reproducible_from_the_seed() {
+        let (layouts, transforms) = perturbed_run(7, true);
+        assert_eq!(layouts.len(), 16);
+        assert_eq!((layouts.clone(), transforms.clone()), perturbed_run(7, true));
+        assert_ne!(layouts, perturbed_run(8, true).0);
+    }
+
+    #[test]
+    fn label_perturbations_do_not_change_the_layout() {
+        assert_eq!(perturbed_run(7, true).0, perturbed_run(7, false).0);
+    }
+}
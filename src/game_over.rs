@@ -1,71 +1,145 @@
-use bevy::prelude::*;
-use super::game::{Penalty, GameDuration};
-use std::fs::File;
-use std::fs;
-use std::io::Write;
-use directories::ProjectDirs;
+use bevy::{
+    prelude::*,
+    ecs::system::SystemParam,
+};
+use std::time::Duration;
+use super::daily::DailyChallenge;
+use super::game::{Board, GameDuration, ReplaySeed, RunLog};
+use super::menu::{button_system, MenuScreen, MenuState, NORMAL_BUTTON};
+use super::replay::{Playback, RunRecording};
+use super::score_store::{ScoreRecord, ScoreStore};
 
 use super::{
     despawn_screen,
     GameState,
     GameConfiguraiton,
+    RunGoal,
 };
 
 pub fn game_over_plugin(app: &mut App) {
     app
         .init_resource::<Score>()
-        .add_systems(OnEnter(GameState::GameOver), (calculate_score, game_over_setup, save_score).chain())
-        .add_systems(Update, countdown.run_if(in_state(GameState::GameOver)))
+        .init_resource::<ScoreSaveError>()
+        .add_systems(OnEnter(GameState::GameOver), (calculate_score, save_score, game_over_setup).chain())
+        .add_systems(
+            Update,
+            (
+                game_over_action,
+                keyboard_shortcuts,
+                button_system,
+                countdown.run_if(resource_exists::<GameOverTimer>),
+            )
+                .run_if(in_state(GameState::GameOver)),
+        )
         .add_systems(OnExit(GameState::GameOver), despawn_screen::<OnGameOverScreen>);
 }
 
 #[derive(Component)]
 struct OnGameOverScreen;
 
+// All actions that can be triggered from a button click or keyboard shortcut on the game over screen
+#[derive(Component, Clone, Copy)]
+enum GameOverButtonAction {
+    PlayAgain,
+    // Plays the same table again, generated from the seed of the finished game
+    SameTable,
+    // Watches the recording of the finished game
+    Watch,
+    BackToMenu,
+    Scoreboard,
+}
+
+// Optional delay after which the game returns to the menu on its own
 #[derive(Resource, Deref, DerefMut)]
 struct GameOverTimer(Timer);
 
 #[derive(Resource, Default, Deref, DerefMut)]
 struct Score(f32);
 
+// Reason the last score could not be saved, displayed on the game over screen
+#[derive(Resource, Default, Deref, DerefMut)]
+struct ScoreSaveError(Option<String>);
+
 fn calculate_score(
     mut score: ResMut<Score>,
-    penalty: Res<Penalty>,
+    board: Res<Board>,
     game_duration: Res<GameDuration>,
 ) {
-    let time = game_duration.time.elapsed().as_millis() as f32 / 1000.0 + **penalty as f32;
-    **score = time;
+    **score = board.score(game_duration.time.elapsed());
 }
 
 fn save_score(
     score: Res<Score>,
+    board: Res<Board>,
+    game_duration: Res<GameDuration>,
+    recording: Res<RunRecording>,
     config: Res<GameConfiguraiton>,
+    mut daily: Option<ResMut<DailyChallenge>>,
+    mut save_error: ResMut<ScoreSaveError>,
 ) {
-    if let Some(proj_dirs) = ProjectDirs::from("com", "example", "schulte_table") {
-        let time_str = format!("{:.2} s", **score);
-        let data_dir = proj_dirs.data_dir();
-        let file_path = data_dir.join(&config.score_file_path);
-        fs::create_dir_all(data_dir)
-            .expect("Unable to create score directory");
-        let mut score_file = File::options().append(true).create(true).open(file_path)
-            .expect("Unable to open/create score file");
-        write!(score_file, "{}\n", &time_str)
-            .expect("Unable to write score file");
-        println!("Score saved: {}", time_str);
-    } else {
-        println!("Unable to get project directories");
+    let mut record = ScoreRecord::from_run(
+        &config,
+        game_duration.time.elapsed_secs(),
+        board.penalty().as_secs_f32(),
+        board.mistakes(),
+        **score,
+        board.seed(),
+    );
+    if let Some(daily) = daily.as_mut() {
+        daily.mark(&mut record);
     }
+    let result = ScoreStore::from_config(&config).and_then(|store| {
+        // A replay that cannot be written must not cost the score
+        if config.save_replays {
+            match store.save_replay(&recording.replay) {
+                Ok(name) => record.replay = Some(name),
+                Err(error) => warn!("Unable to save replay: {}", error),
+            }
+        }
+        store.append(&record)
+    });
+    **save_error = match result {
+        Ok(()) => {
+            info!("Score saved: {}", config.goal.format_score(**score));
+            None
+        }
+        Err(error) => {
+            warn!("Unable to save score: {}", error);
+            Some(format!("Unable to save score: {}", error))
+        }
+    };
 }
 
 fn game_over_setup(
     mut commands: Commands,
     score: Res<Score>,
+    save_error: Res<ScoreSaveError>,
+    run_log: Res<RunLog>,
+    board: Res<Board>,
+    daily: Option<Res<DailyChallenge>>,
+    asset_server: Res<AssetServer>,
     config: Res<GameConfiguraiton>,
 ) {
+    let symbol_font = asset_server.load(&config.symbol_font);
+    let button_style = Style {
+        width: Val::Px(250.0),
+        height: Val::Px(65.0),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let button_text_style = TextStyle {
+        font_size: 28.0,
+        color: config.color_text,
+        ..default()
+    };
+
     commands
         .spawn((
             NodeBundle {
                 style: Style {
+                    flex_direction: FlexDirection::Column,
                     align_items: AlignItems::Center,
                     justify_content: JustifyContent::Center,
                     width: Val::Percent(100.0),
@@ -79,7 +153,10 @@ fn game_over_setup(
         .with_children(|parent| {
             parent.spawn(
                 TextBundle::from_section(
-                    format!("Time: {:.2} s", **score),
+                    match config.goal {
+                        RunGoal::FinishTable => format!("Time: {:.2} s", **score),
+                        _ => format!("Found: {}", **score),
+                    },
                     TextStyle {
                         font_size: 67.0,
                         color: config.color_text,
@@ -91,8 +168,214 @@ fn game_over_setup(
                     ..default()
                 }),
             );
+            spawn_run_breakdown(parent, &run_log, board.penalty(), symbol_font, &config);
+            // Entering the seed in the settings lets other players try the same table
+            let seed = match &daily {
+                Some(daily) => format!("{}, seed: {}", daily.describe_last(), board.seed()),
+                None => format!("Seed: {}", board.seed()),
+            };
+            parent.spawn(TextBundle::from_section(
+                seed,
+                TextStyle {
+                    font_size: 20.0,
+                    color: config.color_text,
+                    ..default()
+                },
+            ));
+            if let Some(error) = &**save_error {
+                parent.spawn(TextBundle::from_section(
+                    error,
+                    TextStyle {
+                        font_size: 20.0,
+                        color: config.color_incorrect,
+                        ..default()
+                    },
+                ));
+            }
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_wrap: FlexWrap::Wrap,
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for (action, text) in [
+                        (GameOverButtonAction::PlayAgain, "Play again (R)"),
+                        (GameOverButtonAction::SameTable, "Same table (T)"),
+                        (GameOverButtonAction::Watch, "Watch replay (W)"),
+                        (GameOverButtonAction::BackToMenu, "Menu (Esc)"),
+                        (GameOverButtonAction::Scoreboard, "Scoreboard (S)"),
+                    ] {
+                        parent
+                            .spawn((
+                                ButtonBundle {
+                                    style: button_style.clone(),
+                                    background_color: NORMAL_BUTTON.into(),
+                                    ..default()
+                                },
+                                action,
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    text,
+                                    button_text_style.clone(),
+                                ));
+                            });
+                    }
+                });
         });
-    commands.insert_resource(GameOverTimer(Timer::from_seconds(2.0, TimerMode::Once)));
+    if config.game_over_auto_return > 0.0 {
+        commands.insert_resource(GameOverTimer(Timer::from_seconds(
+            config.game_over_auto_return,
+            TimerMode::Once,
+        )));
+    } else {
+        commands.remove_resource::<GameOverTimer>();
+    }
+}
+
+const CHART_WIDTH: f32 = 600.0;
+const CHART_HEIGHT: f32 = 150.0;
+
+// Split time statistics and a bar chart of the time needed to find each number
+fn spawn_run_breakdown(
+    parent: &mut ChildBuilder,
+    run_log: &RunLog,
+    penalty: Duration,
+    symbol_font: Handle<Font>,
+    config: &GameConfiguraiton,
+) {
+    let splits = run_log.splits();
+    let Some(&(slowest_target, slowest_split)) = splits.iter().max_by_key(|(_, split)| *split) else {
+        return;
+    };
+    let total: Duration = splits.iter().map(|(_, split)| *split).sum();
+    let average = total / splits.len() as u32;
+    let lost = run_log.recovery_time().saturating_add(penalty);
+    // Labels may use an alphabet that only the symbol font can render
+    let text_style = TextStyle {
+        font: symbol_font,
+        font_size: 25.0,
+        color: config.color_text,
+    };
+
+    for line in [
+        format!("Average per number: {:.2} s", average.as_secs_f32()),
+        format!(
+            "Slowest number: {} ({:.2} s)",
+            config.describe(slowest_target),
+            slowest_split.as_secs_f32(),
+        ),
+        format!("Time lost to mistakes: {:.2} s", lost.as_secs_f32()),
+    ] {
+        parent.spawn(TextBundle::from_section(line, text_style.clone()));
+    }
+
+    // Bars are aligned to the bottom and scaled relative to the slowest number
+    let bar_width = CHART_WIDTH / splits.len() as f32;
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Px(CHART_WIDTH),
+                height: Val::Px(CHART_HEIGHT),
+                align_items: AlignItems::FlexEnd,
+                margin: UiRect::all(Val::Px(20.0)),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|chart| {
+            for (target, split) in splits.iter() {
+                let color = if *target == slowest_target {
+                    config.color_incorrect
+                } else {
+                    config.color_default
+                };
+                chart.spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Px(bar_width),
+                        height: Val::Percent(
+                            100.0 * split.as_secs_f32() / slowest_split.as_secs_f32().max(f32::EPSILON),
+                        ),
+                        border: UiRect::horizontal(Val::Px(1.0)),
+                        ..default()
+                    },
+                    background_color: color.into(),
+                    border_color: config.color_background.into(),
+                    ..default()
+                });
+            }
+        });
+}
+
+fn game_over_action(
+    interaction_query: Query<
+        (&Interaction, &GameOverButtonAction),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut actions: GameOverActions,
+) {
+    for (interaction, action) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            actions.apply(*action);
+        }
+    }
+}
+
+fn keyboard_shortcuts(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut actions: GameOverActions,
+) {
+    let action = if keys.any_just_pressed([KeyCode::KeyR, KeyCode::Enter, KeyCode::Space]) {
+        GameOverButtonAction::PlayAgain
+    } else if keys.just_pressed(KeyCode::KeyT) {
+        GameOverButtonAction::SameTable
+    } else if keys.just_pressed(KeyCode::KeyW) {
+        GameOverButtonAction::Watch
+    } else if keys.any_just_pressed([KeyCode::Escape, KeyCode::KeyM]) {
+        GameOverButtonAction::BackToMenu
+    } else if keys.just_pressed(KeyCode::KeyS) {
+        GameOverButtonAction::Scoreboard
+    } else {
+        return;
+    };
+    actions.apply(action);
+}
+
+// Everything the game over actions change
+#[derive(SystemParam)]
+struct GameOverActions<'w, 's> {
+    commands: Commands<'w, 's>,
+    game_state: ResMut<'w, NextState<GameState>>,
+    menu_screen: ResMut<'w, MenuScreen>,
+    replay_seed: ResMut<'w, ReplaySeed>,
+    board: Res<'w, Board>,
+    recording: Res<'w, RunRecording>,
+}
+
+impl GameOverActions<'_, '_> {
+    fn apply(&mut self, action: GameOverButtonAction) {
+        match action {
+            // Entering the game again sets up a new table with the same settings, unless the seed is fixed
+            GameOverButtonAction::PlayAgain => self.game_state.set(GameState::Game),
+            GameOverButtonAction::SameTable => {
+                **self.replay_seed = Some(self.board.seed());
+                self.game_state.set(GameState::Game);
+            }
+            GameOverButtonAction::Watch => {
+                self.commands.insert_resource(Playback::new(self.recording.replay.clone(), MenuState::Main));
+                self.game_state.set(GameState::Replay);
+            }
+            GameOverButtonAction::BackToMenu => self.game_state.set(GameState::Menu),
+            GameOverButtonAction::Scoreboard => {
+                **self.menu_screen = MenuState::Scoreboard;
+                self.game_state.set(GameState::Menu);
+            }
+        }
+    }
 }
 
 fn countdown(
@@ -0,0 +1,287 @@
+use bevy::prelude::*;
+use std::time::Duration;
+
+use super::board::{ClickOutcome, SchulteBoard, Target};
+use super::game::{Board, GameDuration, RunLog, TileSlot};
+use super::replay::{apply_click, Replay};
+use super::score_store::{ScoreRecord, ScoreStore};
+use super::{
+    despawn_screen,
+    GameState,
+    GameConfiguraiton,
+};
+
+// Opacity of a tile the ghost just clicked, faint enough not to be mistaken for a blink
+const GHOST_ALPHA: f32 = 0.25;
+const GHOST_FLASH_DURATION: f32 = 0.4;
+
+pub fn ghost_plugin(app: &mut App) {
+    app
+        .add_systems(OnEnter(GameState::Game), ghost_hud_setup)
+        .add_systems(
+            Update,
+            (
+                ghost_clicks.run_if(resource_exists::<Ghost>),
+                fade_ghost_flashes,
+                ghost_hud,
+            )
+                .run_if(in_state(GameState::Game)),
+        )
+        .add_systems(OnExit(GameState::Game), despawn_screen::<OnGhostHud>);
+}
+
+#[derive(Component)]
+struct OnGhostHud;
+
+// Faint highlight of a tile clicked by the ghost, removed once it has faded
+#[derive(Component, Deref, DerefMut)]
+struct GhostFlash(Timer);
+
+// Click of the ghost, the target is kept because the tiles may have been reshuffled since
+struct GhostClick {
+    elapsed: Duration,
+    slot: usize,
+    target: Option<Target>,
+    correct: bool,
+}
+
+// Personal best on the same table, raced against during a run
+#[derive(Resource)]
+pub struct Ghost {
+    clicks: Vec<GhostClick>,
+    // Next click to show
+    next: usize,
+    // Stopwatch time at which each number was found
+    found: Vec<Duration>,
+    score: String,
+}
+
+impl Ghost {
+    // Best recorded run with the same rules and seed, if its replay can still be loaded
+    pub fn personal_best(config: &GameConfiguraiton, seed: u64) -> Option<Self> {
+        let store = ScoreStore::from_config(config)
+            .inspect_err(|error| warn!("Unable to load the ghost: {}", error))
+            .ok()?;
+        let records = store.load()
+            .inspect_err(|error| warn!("Unable to load the ghost: {}", error))
+            .ok()?;
+        let bucket = ScoreRecord::from_run(config, 0.0, 0.0, 0, 0.0, seed).bucket();
+        let best = records.into_iter()
+            .filter(|record| !record.abandoned && record.seed == Some(seed) && record.replay.is_some())
+            // Daily challenges are grouped on their own, the rules of their day still have to match
+            .filter(|record| ScoreRecord { daily: None, ..record.clone() }.bucket() == bucket)
+            .min_by(|a, b| {
+                let order = a.score.total_cmp(&b.score);
+                if config.goal.counts_found() { order.reverse() } else { order }
+            })?;
+        // Invalid replays are rejected when loaded, the run is then raced without a ghost
+        let replay = store.load_replay(best.replay.as_deref()?)
+            .inspect_err(|error| warn!("Unable to load the ghost: {}", error))
+            .ok()?;
+        if replay.seed != seed {
+            warn!("Unable to load the ghost: the replay of the best run has seed {}", replay.seed);
+            return None;
+        }
+        Some(Ghost::from_replay(&replay, best.format_score()))
+    }
+
+    // Plays the replay on a board of its own to know what every click hit
+    fn from_replay(replay: &Replay, score: String) -> Self {
+        let mut board = SchulteBoard::new(replay.config.board_rules(), replay.seed);
+        let mut clicks = Vec::new();
+        let mut found = Vec::new();
+        for click in &replay.clicks {
+            let Some(tile) = board.cells().get(click.slot) else {
+                continue;
+            };
+            let target = tile.target;
+            let correct = match apply_click(&mut board, click).outcome {
+                ClickOutcome::Correct(_) => true,
+                ClickOutcome::Mistake { .. } => false,
+                ClickOutcome::Ignored => continue,
+            };
+            if correct {
+                found.push(click.elapsed());
+            }
+            clicks.push(GhostClick {
+                elapsed: click.elapsed(),
+                slot: click.slot,
+                target,
+                correct,
+            });
+        }
+        Ghost {
+            clicks,
+            next: 0,
+            found,
+            score,
+        }
+    }
+}
+
+// Lights the tiles the ghost clicked once the stopwatch reaches the time it clicked them
+fn ghost_clicks(
+    mut commands: Commands,
+    mut ghost: ResMut<Ghost>,
+    tile_query: Query<(Entity, &TileSlot), With<Button>>,
+    board: Res<Board>,
+    game_duration: Res<GameDuration>,
+    config: Res<GameConfiguraiton>,
+) {
+    let ghost = &mut *ghost;
+    let elapsed = game_duration.time.elapsed();
+    while let Some(click) = ghost.clicks.get(ghost.next) {
+        if click.elapsed > elapsed {
+            break;
+        }
+        ghost.next += 1;
+        // Found tiles move around, the one with the same number is where the ghost would have clicked
+        let tile = tile_query.iter().find(|(_, slot)| match click.target {
+            Some(target) => board.cells().get(***slot).and_then(|tile| tile.target) == Some(target),
+            None => ***slot == click.slot,
+        });
+        let Some((entity, _)) = tile else {
+            continue;
+        };
+        let color = if click.correct { config.color_correct } else { config.color_incorrect };
+        commands.entity(entity).with_children(|parent| {
+            parent.spawn((
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        width: Val::Percent(100.0),
+                        height: Val::Percent(100.0),
+                        ..default()
+                    },
+                    background_color: color.with_alpha(GHOST_ALPHA).into(),
+                    // Below the label of the tile
+                    z_index: ZIndex::Local(-1),
+                    ..default()
+                },
+                GhostFlash(Timer::from_seconds(GHOST_FLASH_DURATION, TimerMode::Once)),
+            ));
+        });
+    }
+}
+
+fn fade_ghost_flashes(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut flash_query: Query<(Entity, &mut GhostFlash, &mut BackgroundColor)>,
+) {
+    for (entity, mut flash, mut background) in &mut flash_query {
+        if flash.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        } else {
+            background.0.set_alpha(GHOST_ALPHA * flash.fraction_remaining());
+        }
+    }
+}
+
+fn ghost_hud_setup(mut commands: Commands, config: Res<GameConfiguraiton>) {
+    if !config.ghost {
+        return;
+    }
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 25.0,
+                color: config.color_text,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(20.0),
+            left: Val::Px(20.0),
+            ..default()
+        }),
+        OnGhostHud,
+    ));
+}
+
+// Difference to the ghost at the last number found, negative while ahead of it
+fn ghost_hud(
+    mut hud_query: Query<&mut Text, With<OnGhostHud>>,
+    ghost: Option<Res<Ghost>>,
+    run_log: Res<RunLog>,
+    config: Res<GameConfiguraiton>,
+) {
+    let Ok(mut text) = hud_query.get_single_mut() else {
+        return;
+    };
+    let section = &mut text.sections[0];
+    let Some(ghost) = ghost else {
+        section.value = "Ghost: no previous run on this table".to_string();
+        section.style.color = config.color_text;
+        return;
+    };
+    let found = run_log.clicks.iter().filter(|click| click.correct).map(|click| click.time);
+    let Some((index, time)) = found.enumerate().last() else {
+        section.value = format!("Ghost: {}", ghost.score);
+        section.style.color = config.color_text;
+        return;
+    };
+    let Some(&ghost_time) = ghost.found.get(index) else {
+        section.value = "Ghost: passed".to_string();
+        section.style.color = config.color_correct;
+        return;
+    };
+    let (sign, delta, color) = if time <= ghost_time {
+        ('-', ghost_time - time, config.color_correct)
+    } else {
+        ('+', time - ghost_time, config.color_incorrect)
+    };
+    section.value = format!("Ghost: {}{:.2} s", sign, delta.as_secs_f32());
+    section.style.color = color;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::RunRecording;
+    use crate::ShuffleMode;
+
+    #[test]
+    fn ghost_follows_reshuffled_tables() {
+        let config = GameConfiguraiton {
+            width: 4,
+            height: 4,
+            shuffle: ShuffleMode::All,
+            ..default()
+        };
+        let mut board = SchulteBoard::new(config.board_rules(), 5);
+        let mut recording = RunRecording::new(5, &config);
+        let mut expected = Vec::new();
+        let mut found = Vec::new();
+        for index in 0.. {
+            let Some(next) = board.next_target() else {
+                break;
+            };
+            let elapsed = Duration::from_millis(250 * index);
+            // Every fifth click misses the next number
+            let mistake = index % 5 == 4;
+            let slot = board.cells().iter()
+                .position(|tile| (tile.target == Some(next)) != mistake)
+                .unwrap();
+            expected.push((slot, board.cells()[slot].target, !mistake));
+            if !mistake {
+                found.push(elapsed);
+            }
+            recording.click(slot, elapsed);
+            if matches!(board.click(slot, elapsed), ClickOutcome::Correct(_)) {
+                board.reshuffle();
+            }
+        }
+
+        let ghost = Ghost::from_replay(&recording.replay, "1.00 s".to_string());
+        assert_eq!(ghost.found, found);
+        assert_eq!(ghost.found.len(), 16);
+        let clicks: Vec<_> = ghost.clicks.iter().map(|click| (click.slot, click.target, click.correct)).collect();
+        assert_eq!(clicks, expected);
+        // Reshuffling sends the numbers to other slots than the ones they started in
+        let first_table = SchulteBoard::new(config.board_rules(), 5);
+        assert!(ghost.clicks.iter().any(|click| first_table.cells()[click.slot].target != click.target));
+    }
+}
@@ -1,34 +1,217 @@
+// Bevy queries and systems routinely trip these lints, see https://github.com/bevyengine/bevy/issues/4601
+#![allow(clippy::type_complexity, clippy::too_many_arguments)]
+
 use bevy::{
     prelude::*,
     window::WindowMode,
 };
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
 
+pub mod board;
+mod config;
+mod daily;
 mod menu;
 mod game;
 mod game_over;
+mod ghost;
+mod replay;
+mod score_store;
+mod symbols;
+
+pub use symbols::SymbolSet;
+
+use board::{BoardRules, Target};
 
-#[derive(Resource, Clone)]
+// Bounds for the table dimensions and number sequence; the largest table still has to fit the `u16` tile numbers
+pub const MIN_GRID_SIZE: usize = 2;
+pub const MAX_GRID_SIZE: usize = 10;
+pub const MAX_SEQUENCE_START: u16 = 100;
+pub const MAX_SEQUENCE_STEP: u16 = 10;
+pub const MAX_AUTO_RETURN: f32 = 30.0;
+pub const MAX_COUNTDOWN: u8 = 5;
+pub const MAX_SHUFFLE_ANIMATION: f32 = 1.0;
+pub const MAX_MEMORY_PREVIEW: u8 = 30;
+pub const MAX_DISTRACTORS: u8 = 50;
+pub const MAX_PERTURBATION: u8 = 10;
+pub const MAX_INCORRECT_PENALTY: u8 = 30;
+pub const DEFAULT_INCORRECT_PENALTY: u8 = 2;
+pub const DEFAULT_PENALTY_PERCENT: u8 = 5;
+pub const MAX_PENALTY_PERCENT: u8 = 50;
+pub const MIN_TIME_LIMIT: u16 = 10;
+pub const MAX_TIME_LIMIT: u16 = 600;
+pub const MAX_MISTAKE_LIMIT: u32 = 20;
+pub const MIN_GAZE_RADIUS: f32 = 40.0;
+pub const MAX_GAZE_RADIUS: f32 = 400.0;
+// Bounds of the settings only editable in the configuration file
+pub const MAX_TIMER_DURATION: f32 = 5.0;
+pub const MIN_BUTTON_SIZE: f32 = 10.0;
+pub const MAX_BUTTON_SIZE: f32 = 300.0;
+pub const MAX_BUTTON_PADDING: f32 = 50.0;
+pub const MIN_FONT_SIZE: f32 = 5.0;
+pub const MAX_FONT_SIZE: f32 = 200.0;
+
+#[derive(Resource, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct GameConfiguraiton {
+    pub mode: GameMode,
+    // Numbers of a classic table: `sequence_start`, then every `sequence_step`, optionally clicked in reverse
+    pub sequence_start: u16,
+    pub sequence_step: u16,
+    pub sequence_descending: bool,
+    pub symbols: SymbolSet,
+    // Ordered symbols of `SymbolSet::Custom`
+    pub custom_symbols: Vec<String>,
+    // Font asset used for labels the default font cannot render
+    pub symbol_font: String,
     pub width: usize,
     pub height: usize,
+    // Tiles that are not part of the sequence and count as mistakes, at most half of the table
+    pub distractors: u8,
+    pub distractor_kind: DistractorKind,
+    pub goal: RunGoal,
+    // Seconds available in `RunGoal::TimeAttack`, mistakes penalties are taken from them
+    pub time_limit: u16,
+    // Mistakes that end a `RunGoal::Endless` run
+    pub mistake_limit: u32,
+    pub shuffle: ShuffleMode,
+    // Seconds a tile takes to move to its new place, 0 moves it instantly
+    pub shuffle_animation: f32,
+    pub fixation: FixationMode,
+    // Pixels around the cursor in which labels are readable with `FixationMode::Gaze`
+    pub gaze_radius: f32,
+    // Random distortion of each label, levels from 0 (off) to `MAX_PERTURBATION`
+    pub perturb_rotation: u8,
+    pub perturb_size: u8,
+    pub perturb_color: u8,
+    pub perturb_mirror: bool,
+    // Seed every table is generated from, `None` picks a new random seed for each game
+    #[serde(with = "config::seed")]
+    pub seed: Option<u64>,
+    // Write a replay file next to the scores for every finished game
+    pub save_replays: bool,
+    // Race the replay of the personal best when the same table is played again
+    pub ghost: bool,
     pub button_size: f32,
     pub button_padding: f32,
     pub font_size: f32,
+    #[serde(with = "config::hex_color")]
     pub color_correct: Color,
+    #[serde(with = "config::hex_color")]
     pub color_incorrect: Color,
+    #[serde(with = "config::hex_color")]
     pub color_default: Color,
+    #[serde(with = "config::hex_color")]
     pub color_text: Color,
+    #[serde(with = "config::hex_color")]
     pub color_background: Color,
+    // Label colors of the two interleaved sequences of the Gorbov mode
+    #[serde(with = "config::hex_color")]
+    pub color_gorbov_black: Color,
+    #[serde(with = "config::hex_color")]
+    pub color_gorbov_red: Color,
+    #[serde(with = "config::hex_color")]
+    pub color_fixation: Color,
     pub timer_duration: f32,
+    pub penalty_policy: PenaltyPolicy,
+    // Seconds per mistake of the flat and escalating penalties
     pub incorrect_penalty: u8,
+    // Share of the elapsed time added per mistake by `PenaltyPolicy::Percent`
+    pub penalty_percent: u8,
     pub score_file_path: String,
+    // Seconds after which the game over screen returns to the menu, 0 waits for the player
+    pub game_over_auto_return: f32,
+    // Seconds of "3, 2, 1" before the table is revealed, 0 reveals it immediately
+    pub countdown: u8,
+    // Seconds the numbers of a memory table are shown before they are hidden
+    pub memory_preview: u8,
+    // Start the stopwatch on the first correct click instead of when the numbers appear
+    pub start_timer_on_first_click: bool,
+    // Elements of the in-game HUD, showing the next number changes the exercise
+    pub hud_timer: bool,
+    pub hud_next_number: bool,
+    pub hud_progress: bool,
+    pub hud_mistakes: bool,
+}
+
+impl GameConfiguraiton {
+    pub fn perturbation(&self) -> Perturbation {
+        Perturbation {
+            rotation: self.perturb_rotation,
+            size: self.perturb_size,
+            color: self.perturb_color,
+            mirror: self.perturb_mirror,
+        }
+    }
+
+    // Rules of the board model, everything else in the configuration only affects the display
+    pub fn board_rules(&self) -> BoardRules {
+        BoardRules {
+            width: self.width,
+            height: self.height,
+            mode: self.mode,
+            sequence: self.sequence(),
+            symbols: self.symbols,
+            custom_symbols: self.custom_symbols.clone(),
+            distractors: self.distractors as usize,
+            distractor_kind: self.distractor_kind,
+            shuffle: self.shuffle,
+            penalty_policy: self.penalty_policy,
+            incorrect_penalty: self.incorrect_penalty,
+            penalty_percent: self.penalty_percent,
+            goal: self.goal,
+            time_limit: self.time_limit,
+            mistake_limit: self.mistake_limit,
+        }
+    }
+
+    // Text written on the tile of a target
+    pub fn label(&self, target: Target) -> String {
+        target.label(self.symbols, &self.custom_symbols)
+    }
+
+    // Description of a target outside of the table
+    pub fn describe(&self, target: Target) -> String {
+        target.describe(self.symbols, &self.custom_symbols)
+    }
+
+    pub fn sequence(&self) -> SequenceRule {
+        SequenceRule {
+            start: self.sequence_start,
+            step: self.sequence_step,
+            descending: self.sequence_descending,
+        }
+    }
 }
 
 impl Default for GameConfiguraiton {
     fn default() -> Self {
         GameConfiguraiton {
+            mode: GameMode::Classic,
+            sequence_start: 1,
+            sequence_step: 1,
+            sequence_descending: false,
+            symbols: SymbolSet::Numbers,
+            custom_symbols: Vec::new(),
+            symbol_font: "fonts/DejaVuSans.ttf".to_string(),
             width: 2,
             height: 2,
+            distractors: 0,
+            distractor_kind: DistractorKind::OutOfRange,
+            goal: RunGoal::FinishTable,
+            time_limit: 60,
+            mistake_limit: 3,
+            shuffle: ShuffleMode::Off,
+            shuffle_animation: 0.3,
+            fixation: FixationMode::Off,
+            gaze_radius: 120.0,
+            perturb_rotation: 0,
+            perturb_size: 0,
+            perturb_color: 0,
+            perturb_mirror: false,
+            seed: None,
+            save_replays: true,
+            ghost: false,
             button_size: 50.0,
             button_padding: 5.0,
             font_size: 30.0,
@@ -37,9 +220,215 @@ impl Default for GameConfiguraiton {
             color_default: Color::srgb(0.5, 0.5, 0.5),
             color_text: Color::WHITE,
             color_background: Color::srgb(0.9, 0.9, 0.9),
+            color_gorbov_black: Color::BLACK,
+            color_gorbov_red: Color::srgb(0.85, 0.1, 0.1),
+            color_fixation: Color::srgb(0.85, 0.1, 0.1),
             timer_duration: 0.2,
-            incorrect_penalty: 2,
+            penalty_policy: PenaltyPolicy::Flat,
+            incorrect_penalty: DEFAULT_INCORRECT_PENALTY,
+            penalty_percent: DEFAULT_PENALTY_PERCENT,
             score_file_path: "score/score.txt".to_string(),
+            game_over_auto_return: 0.0,
+            countdown: 3,
+            memory_preview: 5,
+            start_timer_on_first_click: false,
+            hud_timer: true,
+            hud_next_number: false,
+            hud_progress: false,
+            hud_mistakes: false,
+        }
+    }
+}
+
+// Rules a table is played with, recorded with every score so different modes are never compared
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum GameMode {
+    #[default]
+    Classic,
+    // Gorbov-Schulte table: alternate between black numbers ascending and red numbers descending
+    Gorbov,
+    // Classic numbers shown for a short preview, then clicked from memory
+    Memory,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 3] = [GameMode::Classic, GameMode::Gorbov, GameMode::Memory];
+
+    // Modes played with the numbers of `SequenceRule`
+    pub fn uses_sequence(&self) -> bool {
+        matches!(self, GameMode::Classic | GameMode::Memory)
+    }
+}
+
+// When a run ends and what its score measures
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum RunGoal {
+    // Find every number of one table, the score is the time taken
+    #[default]
+    FinishTable,
+    // Find as many numbers as possible within `time_limit`
+    TimeAttack,
+    // Tables follow each other until `mistake_limit` mistakes are made
+    Endless,
+}
+
+impl RunGoal {
+    pub const ALL: [RunGoal; 3] = [RunGoal::FinishTable, RunGoal::TimeAttack, RunGoal::Endless];
+
+    // Whether the score counts the numbers found, higher being better, instead of seconds
+    pub fn counts_found(&self) -> bool {
+        *self != RunGoal::FinishTable
+    }
+
+    pub fn score(&self, time: f32, penalty: f32, found: u32) -> f32 {
+        if self.counts_found() {
+            found as f32
+        } else {
+            time + penalty
+        }
+    }
+
+    pub fn format_score(&self, score: f32) -> String {
+        if self.counts_found() {
+            format!("{} found", score)
+        } else {
+            format!("{:.2} s", score)
+        }
+    }
+}
+
+// What the distractor tiles show
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum DistractorKind {
+    // Numbers past the end of the sequence
+    #[default]
+    OutOfRange,
+    // Letters among numbers, or numbers among the symbols of other sets
+    OtherSymbols,
+    // Labels of the table with a character replaced by a similar looking one, like 1 and I
+    Lookalikes,
+}
+
+impl DistractorKind {
+    pub const ALL: [DistractorKind; 3] = [
+        DistractorKind::OutOfRange,
+        DistractorKind::OtherSymbols,
+        DistractorKind::Lookalikes,
+    ];
+}
+
+// What a wrong click costs
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum PenaltyPolicy {
+    // `incorrect_penalty` seconds per mistake
+    #[default]
+    Flat,
+    // `penalty_percent` of the time elapsed when the mistake is made
+    Percent,
+    // `incorrect_penalty` seconds times the number of consecutive mistakes
+    Escalating,
+    // No time is added but the sequence has to be found again from the start
+    RestartSequence,
+    // Mistakes are only counted and reported next to the score
+    None,
+}
+
+impl PenaltyPolicy {
+    pub const ALL: [PenaltyPolicy; 5] = [
+        PenaltyPolicy::Flat,
+        PenaltyPolicy::Percent,
+        PenaltyPolicy::Escalating,
+        PenaltyPolicy::RestartSequence,
+        PenaltyPolicy::None,
+    ];
+
+    // Whether the cost of a mistake is set by `incorrect_penalty`
+    pub fn uses_seconds(&self) -> bool {
+        matches!(self, PenaltyPolicy::Flat | PenaltyPolicy::Escalating)
+    }
+
+    // Whether the cost of a mistake is set by `penalty_percent`
+    pub fn uses_percent(&self) -> bool {
+        *self == PenaltyPolicy::Percent
+    }
+}
+
+// Which tiles trade places after each correct click
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ShuffleMode {
+    #[default]
+    Off,
+    // Only the tiles that still have to be found
+    Remaining,
+    All,
+}
+
+impl ShuffleMode {
+    pub const ALL: [ShuffleMode; 3] = [ShuffleMode::Off, ShuffleMode::Remaining, ShuffleMode::All];
+}
+
+// How the table enforces reading with the eyes fixed on its center
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum FixationMode {
+    #[default]
+    Off,
+    // Fixation marker in the center, labels get smaller and fainter towards the edges
+    Peripheral,
+    // Fixation marker in the center, labels are only shown close to the cursor
+    Gaze,
+}
+
+impl FixationMode {
+    pub const ALL: [FixationMode; 3] = [FixationMode::Off, FixationMode::Peripheral, FixationMode::Gaze];
+}
+
+// How much the labels are rotated, resized, recolored and mirrored, like advanced printed tables
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Perturbation {
+    pub rotation: u8,
+    pub size: u8,
+    pub color: u8,
+    pub mirror: bool,
+}
+
+// Numbers expected by a classic table, e.g. only even numbers or counting down
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct SequenceRule {
+    pub start: u16,
+    pub step: u16,
+    pub descending: bool,
+}
+
+impl Default for SequenceRule {
+    fn default() -> Self {
+        SequenceRule {
+            start: 1,
+            step: 1,
+            descending: false,
+        }
+    }
+}
+
+impl SequenceRule {
+    // The `count` numbers of the table in the order they have to be clicked
+    pub fn numbers(&self, count: usize) -> Vec<u16> {
+        let mut numbers: Vec<u16> = (0..count as u16)
+            .map(|index| self.start + index * self.step)
+            .collect();
+        if self.descending {
+            numbers.reverse();
+        }
+        numbers
+    }
+}
+
+impl std::fmt::Display for SequenceRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let [first, second, third] = [0, 1, 2].map(|index| self.start + index * self.step);
+        if self.descending {
+            write!(f, "..., {}, {}, {}", third, second, first)
+        } else {
+            write!(f, "{}, {}, {}, ...", first, second, third)
         }
     }
 }
@@ -50,6 +439,20 @@ enum GameState {
     Menu,
     Game,
     GameOver,
+    // Playback of a recorded run
+    Replay,
+}
+
+// Phase of a running game, the table is only revealed once the countdown is over
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, SubStates)]
+#[source(GameState = GameState::Game)]
+enum GamePhase {
+    #[default]
+    Countdown,
+    // Memory mode only: the numbers are shown to be memorized before the clock starts
+    Preview,
+    Playing,
+    Paused,
 }
 
 #[bevy_main]
@@ -65,34 +468,38 @@ fn main() {
                     }),
                     ..default()
                 }),
-            GamePlugin::default(),
+            GamePlugin,
         ))
         .run();
 }
 
-pub struct GamePlugin {
-    configuration: GameConfiguraiton,
-}
-
-impl Default for GamePlugin {
-    fn default() -> Self {
-        GamePlugin {
-            configuration: GameConfiguraiton::default(),
-        }
-    }
-}
+#[derive(Default)]
+pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
+        let (configuration, warnings) = config::load_configuration();
         app
-            .insert_resource(self.configuration.clone())
-            .insert_resource(ClearColor(self.configuration.color_background))
+            .insert_resource(ClearColor(configuration.color_background))
+            .insert_resource(configuration)
+            .insert_resource(warnings)
             .init_state::<GameState>()
+            .add_sub_state::<GamePhase>()
             .add_systems(Startup, setup)
-            .add_plugins((menu::menu_plugin, game::game_plugin, game_over::game_over_plugin));
+            .add_plugins((
+                menu::menu_plugin,
+                game::game_plugin,
+                game_over::game_over_plugin,
+                daily::daily_plugin,
+                replay::replay_plugin,
+                ghost::ghost_plugin,
+            ));
     }
 }
 
+fn project_dirs() -> Option<ProjectDirs> {
+    ProjectDirs::from("com", "example", "schulte_table")
+}
 
 fn setup(mut commands: Commands) {
     commands.spawn(Camera2dBundle::default());
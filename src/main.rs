@@ -5,7 +5,7 @@ fn main() {
     App::new()
         .add_plugins((
             DefaultPlugins,
-            GamePlugin::default(),
+            GamePlugin,
         ))
         .run();
 }
@@ -2,24 +2,59 @@ use bevy::{
     prelude::*,
     app::AppExit,
 };
-use std::fs;
+use chrono::Local;
+use rand::{thread_rng, Rng};
+use std::collections::BTreeMap;
 
 use super::{
+    config::{save_configuration, ConfigWarnings},
+    daily::{self, DailyChallenge},
     despawn_screen,
+    game::HudElement,
+    replay::Playback,
+    score_store::{ScoreBucket, ScoreRecord, ScoreStore},
     GameState,
     GameConfiguraiton,
+    DistractorKind,
+    FixationMode,
+    GameMode,
+    PenaltyPolicy,
+    RunGoal,
+    ShuffleMode,
+    SymbolSet,
+    MIN_GRID_SIZE,
+    MAX_GRID_SIZE,
+    MAX_SEQUENCE_START,
+    MAX_SEQUENCE_STEP,
+    MAX_AUTO_RETURN,
+    MAX_COUNTDOWN,
+    MAX_SHUFFLE_ANIMATION,
+    MAX_MEMORY_PREVIEW,
+    MIN_GAZE_RADIUS,
+    MAX_GAZE_RADIUS,
+    MIN_TIME_LIMIT,
+    MAX_TIME_LIMIT,
+    MAX_MISTAKE_LIMIT,
+    MAX_INCORRECT_PENALTY,
+    MAX_PENALTY_PERCENT,
+    MAX_DISTRACTORS,
+    MAX_PERTURBATION,
 };
 
 pub fn menu_plugin(app: &mut App) {
     app
         .init_state::<MenuState>()
+        .init_resource::<MenuScreen>()
         .add_systems(OnEnter(GameState::Menu), menu_setup)
         // Systems to handle the main menu screen
         .add_systems(OnEnter(MenuState::Main), main_menu_setup)
         .add_systems(OnExit(MenuState::Main), despawn_screen::<OnMainMenuScreen>)
+        .init_resource::<Scoreboard>()
+        .init_resource::<ScoreboardView>()
         .add_systems(OnEnter(MenuState::Scoreboard), scoreboard_setup)
         .add_systems(OnExit(MenuState::Scoreboard), despawn_screen::<OnScoreboardScreen>)
         // Systems to handle the settings menu screen
+        .init_resource::<SettingsPage>()
         .add_systems(OnEnter(MenuState::Settings), settings_menu_setup)
         .add_systems(
             OnExit(MenuState::Settings),
@@ -29,12 +64,40 @@ pub fn menu_plugin(app: &mut App) {
         .add_systems(
             Update,
             (menu_action, button_system).run_if(in_state(GameState::Menu)),
+        )
+        .add_systems(
+            Update,
+            scoreboard_table_system
+                .after(menu_action)
+                .run_if(in_state(MenuState::Scoreboard))
+                .run_if(resource_changed::<ScoreboardView>),
+        )
+        .add_systems(
+            Update,
+            settings_rows_system
+                .after(menu_action)
+                .run_if(in_state(MenuState::Settings))
+                .run_if(resource_changed::<SettingsPage>),
+        )
+        .add_systems(
+            Update,
+            seed_input
+                .before(setting_value_system)
+                .before(save_settings)
+                .run_if(in_state(MenuState::Settings)),
+        )
+        .add_systems(
+            Update,
+            (setting_value_system, save_settings)
+                .after(menu_action)
+                .run_if(in_state(MenuState::Settings))
+                .run_if(resource_changed::<GameConfiguraiton>),
         );
 }
 
 // State used for the current menu screen
 #[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States)]
-enum MenuState {
+pub enum MenuState {
     Main,
     Settings,
     Scoreboard,
@@ -42,6 +105,16 @@ enum MenuState {
     Disabled,
 }
 
+// Menu screen shown the next time the menu is entered
+#[derive(Resource, Deref, DerefMut)]
+pub struct MenuScreen(pub MenuState);
+
+impl Default for MenuScreen {
+    fn default() -> Self {
+        MenuScreen(MenuState::Main)
+    }
+}
+
 #[derive(Component)]
 struct OnMainMenuScreen;
 
@@ -51,28 +124,428 @@ struct OnSettingsMenuScreen;
 #[derive(Component)]
 struct OnScoreboardScreen;
 
-const NORMAL_BUTTON: Color = Color::srgb(0.15, 0.15, 0.15);
+// Tag component used to mark the node holding the currently displayed scores
+#[derive(Component)]
+struct ScoreboardTable;
+
+const SCOREBOARD_PAGE_SIZE: usize = 10;
+
+// Scores loaded when entering the scoreboard, grouped by comparable games and sorted best first
+#[derive(Resource, Default)]
+struct Scoreboard {
+    buckets: Vec<(ScoreBucket, Vec<ScoreRecord>)>,
+    error: Option<String>,
+}
+
+impl Scoreboard {
+    fn from_records(records: Vec<ScoreRecord>) -> Self {
+        let mut buckets: BTreeMap<ScoreBucket, Vec<ScoreRecord>> = BTreeMap::new();
+        for record in records.into_iter().filter(|record| !record.abandoned && !record.practice) {
+            buckets.entry(record.bucket()).or_default().push(record);
+        }
+        for (bucket, records) in buckets.iter_mut() {
+            // Every day has a different table, so the daily history is listed newest first
+            if bucket.daily {
+                records.sort_by_key(|record| std::cmp::Reverse(record.daily));
+            } else if bucket.goal.unwrap_or_default().counts_found() {
+                records.sort_by(|a, b| b.score.total_cmp(&a.score));
+            } else {
+                records.sort_by(|a, b| a.score.total_cmp(&b.score));
+            }
+        }
+        Scoreboard {
+            buckets: buckets.into_iter().collect(),
+            error: None,
+        }
+    }
+}
+
+// Which group and page of the scoreboard is displayed
+#[derive(Resource, Default)]
+struct ScoreboardView {
+    bucket: usize,
+    page: usize,
+}
+
+pub const NORMAL_BUTTON: Color = Color::srgb(0.15, 0.15, 0.15);
 const HOVERED_BUTTON: Color = Color::srgb(0.25, 0.25, 0.25);
 const HOVERED_PRESSED_BUTTON: Color = Color::srgb(0.25, 0.65, 0.25);
 const PRESSED_BUTTON: Color = Color::srgb(0.35, 0.75, 0.35);
 
 // Tag component used to mark which setting is currently selected
 #[derive(Component)]
-struct SelectedOption;
+pub struct SelectedOption;
+
+// Tag component used to mark the node holding the rows of the current settings page
+#[derive(Component)]
+struct SettingsRows;
+
+#[derive(Component)]
+struct SettingsPageTitle;
+
+// Index into `SETTINGS_PAGES` of the displayed settings page
+#[derive(Resource, Default, Deref, DerefMut)]
+struct SettingsPage(usize);
+
+const SETTINGS_PAGES: [(&str, &[SettingValue]); 11] = [
+    (
+        "Table",
+        &[SettingValue::Mode, SettingValue::Symbols, SettingValue::Width, SettingValue::Height],
+    ),
+    (
+        "Sequence",
+        &[SettingValue::SequenceStart, SettingValue::SequenceStep, SettingValue::SequenceOrder],
+    ),
+    ("Distractors", &[SettingValue::Distractors, SettingValue::DistractorKind]),
+    (
+        "Perturbation",
+        &[
+            SettingValue::PerturbRotation,
+            SettingValue::PerturbSize,
+            SettingValue::PerturbColor,
+            SettingValue::PerturbMirror,
+        ],
+    ),
+    (
+        "Mistakes",
+        &[SettingValue::PenaltyPolicy, SettingValue::PenaltySeconds, SettingValue::PenaltyPercent],
+    ),
+    (
+        "Goal",
+        &[SettingValue::Goal, SettingValue::TimeLimit, SettingValue::MistakeLimit],
+    ),
+    ("Dynamic table", &[SettingValue::Shuffle, SettingValue::ShuffleAnimation]),
+    ("Fixation", &[SettingValue::Fixation, SettingValue::GazeRadius]),
+    ("Replays", &[SettingValue::Seed, SettingValue::SaveReplays, SettingValue::Ghost]),
+    (
+        "Timing",
+        &[
+            SettingValue::Countdown,
+            SettingValue::MemoryPreview,
+            SettingValue::TimerStart,
+            SettingValue::AutoReturn,
+        ],
+    ),
+    (
+        "HUD",
+        &[
+            SettingValue::Hud(HudElement::Timer),
+            SettingValue::Hud(HudElement::NextNumber),
+            SettingValue::Hud(HudElement::Progress),
+            SettingValue::Hud(HudElement::Mistakes),
+        ],
+    ),
+];
+
+// Tag component used to mark the text displaying the current value of a setting
+#[derive(Component, Clone, Copy)]
+enum SettingValue {
+    Mode,
+    Symbols,
+    SequenceStart,
+    SequenceStep,
+    SequenceOrder,
+    Width,
+    Height,
+    AutoReturn,
+    Countdown,
+    MemoryPreview,
+    TimerStart,
+    Hud(HudElement),
+    Shuffle,
+    ShuffleAnimation,
+    Fixation,
+    GazeRadius,
+    Goal,
+    TimeLimit,
+    MistakeLimit,
+    PenaltyPolicy,
+    PenaltySeconds,
+    PenaltyPercent,
+    Distractors,
+    DistractorKind,
+    PerturbRotation,
+    PerturbSize,
+    PerturbColor,
+    PerturbMirror,
+    Seed,
+    SaveReplays,
+    Ghost,
+}
+
+impl SettingValue {
+    fn label(&self) -> &'static str {
+        match self {
+            SettingValue::Mode => "Mode",
+            SettingValue::Symbols => "Symbols",
+            SettingValue::SequenceStart => "Start",
+            SettingValue::SequenceStep => "Step",
+            SettingValue::SequenceOrder => "Order",
+            SettingValue::Width => "Width",
+            SettingValue::Height => "Height",
+            SettingValue::AutoReturn => "Auto return",
+            SettingValue::Countdown => "Countdown",
+            SettingValue::MemoryPreview => "Memory preview",
+            SettingValue::TimerStart => "Timer start",
+            SettingValue::Hud(HudElement::Timer) => "Timer",
+            SettingValue::Hud(HudElement::NextNumber) => "Next number",
+            SettingValue::Hud(HudElement::Progress) => "Progress",
+            SettingValue::Hud(HudElement::Mistakes) => "Mistakes",
+            SettingValue::Shuffle => "Reshuffle",
+            SettingValue::ShuffleAnimation => "Animation",
+            SettingValue::Fixation => "Fixation",
+            SettingValue::GazeRadius => "Gaze radius",
+            SettingValue::Goal => "Goal",
+            SettingValue::TimeLimit => "Time limit",
+            SettingValue::MistakeLimit => "Mistake limit",
+            SettingValue::PenaltyPolicy => "Penalty",
+            SettingValue::PenaltySeconds => "Seconds",
+            SettingValue::PenaltyPercent => "Percent",
+            SettingValue::Distractors => "Count",
+            SettingValue::DistractorKind => "Kind",
+            SettingValue::PerturbRotation => "Rotation",
+            SettingValue::PerturbSize => "Size",
+            SettingValue::PerturbColor => "Color",
+            SettingValue::PerturbMirror => "Mirrored",
+            SettingValue::Seed => "Seed (0-9)",
+            SettingValue::SaveReplays => "Save replays",
+            SettingValue::Ghost => "Ghost",
+        }
+    }
+
+    // Actions of the decrement and increment buttons, toggles use the same action for both
+    fn steps(&self) -> (MenuButtonAction, MenuButtonAction) {
+        match self {
+            SettingValue::Mode => (
+                MenuButtonAction::SettingsMode(-1),
+                MenuButtonAction::SettingsMode(1),
+            ),
+            SettingValue::Symbols => (
+                MenuButtonAction::SettingsSymbols(-1),
+                MenuButtonAction::SettingsSymbols(1),
+            ),
+            SettingValue::SequenceStart => (
+                MenuButtonAction::SettingsSequenceStart(-1),
+                MenuButtonAction::SettingsSequenceStart(1),
+            ),
+            SettingValue::SequenceStep => (
+                MenuButtonAction::SettingsSequenceStep(-1),
+                MenuButtonAction::SettingsSequenceStep(1),
+            ),
+            SettingValue::SequenceOrder => (
+                MenuButtonAction::SettingsSequenceOrder,
+                MenuButtonAction::SettingsSequenceOrder,
+            ),
+            SettingValue::Width => (
+                MenuButtonAction::SettingsWidth(-1),
+                MenuButtonAction::SettingsWidth(1),
+            ),
+            SettingValue::Height => (
+                MenuButtonAction::SettingsHeight(-1),
+                MenuButtonAction::SettingsHeight(1),
+            ),
+            SettingValue::AutoReturn => (
+                MenuButtonAction::SettingsAutoReturn(-1.0),
+                MenuButtonAction::SettingsAutoReturn(1.0),
+            ),
+            SettingValue::Countdown => (
+                MenuButtonAction::SettingsCountdown(-1),
+                MenuButtonAction::SettingsCountdown(1),
+            ),
+            SettingValue::MemoryPreview => (
+                MenuButtonAction::SettingsMemoryPreview(-1),
+                MenuButtonAction::SettingsMemoryPreview(1),
+            ),
+            SettingValue::TimerStart => (
+                MenuButtonAction::SettingsTimerStart,
+                MenuButtonAction::SettingsTimerStart,
+            ),
+            SettingValue::Hud(element) => (
+                MenuButtonAction::SettingsHud(*element),
+                MenuButtonAction::SettingsHud(*element),
+            ),
+            SettingValue::Shuffle => (
+                MenuButtonAction::SettingsShuffle(-1),
+                MenuButtonAction::SettingsShuffle(1),
+            ),
+            SettingValue::ShuffleAnimation => (
+                MenuButtonAction::SettingsShuffleAnimation(-0.1),
+                MenuButtonAction::SettingsShuffleAnimation(0.1),
+            ),
+            SettingValue::Fixation => (
+                MenuButtonAction::SettingsFixation(-1),
+                MenuButtonAction::SettingsFixation(1),
+            ),
+            SettingValue::GazeRadius => (
+                MenuButtonAction::SettingsGazeRadius(-20.0),
+                MenuButtonAction::SettingsGazeRadius(20.0),
+            ),
+            SettingValue::Goal => (
+                MenuButtonAction::SettingsGoal(-1),
+                MenuButtonAction::SettingsGoal(1),
+            ),
+            SettingValue::TimeLimit => (
+                MenuButtonAction::SettingsTimeLimit(-10),
+                MenuButtonAction::SettingsTimeLimit(10),
+            ),
+            SettingValue::MistakeLimit => (
+                MenuButtonAction::SettingsMistakeLimit(-1),
+                MenuButtonAction::SettingsMistakeLimit(1),
+            ),
+            SettingValue::PenaltyPolicy => (
+                MenuButtonAction::SettingsPenaltyPolicy(-1),
+                MenuButtonAction::SettingsPenaltyPolicy(1),
+            ),
+            SettingValue::PenaltySeconds => (
+                MenuButtonAction::SettingsPenaltySeconds(-1),
+                MenuButtonAction::SettingsPenaltySeconds(1),
+            ),
+            SettingValue::PenaltyPercent => (
+                MenuButtonAction::SettingsPenaltyPercent(-1),
+                MenuButtonAction::SettingsPenaltyPercent(1),
+            ),
+            SettingValue::Distractors => (
+                MenuButtonAction::SettingsDistractors(-1),
+                MenuButtonAction::SettingsDistractors(1),
+            ),
+            SettingValue::DistractorKind => (
+                MenuButtonAction::SettingsDistractorKind(-1),
+                MenuButtonAction::SettingsDistractorKind(1),
+            ),
+            SettingValue::PerturbRotation => (
+                MenuButtonAction::SettingsPerturbRotation(-1),
+                MenuButtonAction::SettingsPerturbRotation(1),
+            ),
+            SettingValue::PerturbSize => (
+                MenuButtonAction::SettingsPerturbSize(-1),
+                MenuButtonAction::SettingsPerturbSize(1),
+            ),
+            SettingValue::PerturbColor => (
+                MenuButtonAction::SettingsPerturbColor(-1),
+                MenuButtonAction::SettingsPerturbColor(1),
+            ),
+            SettingValue::PerturbMirror => (
+                MenuButtonAction::SettingsPerturbMirror,
+                MenuButtonAction::SettingsPerturbMirror,
+            ),
+            SettingValue::Seed => (
+                MenuButtonAction::SettingsSeedRandom,
+                MenuButtonAction::SettingsSeedNew,
+            ),
+            SettingValue::SaveReplays => (
+                MenuButtonAction::SettingsSaveReplays,
+                MenuButtonAction::SettingsSaveReplays,
+            ),
+            SettingValue::Ghost => (
+                MenuButtonAction::SettingsGhost,
+                MenuButtonAction::SettingsGhost,
+            ),
+        }
+    }
+
+    fn value(&self, config: &GameConfiguraiton) -> String {
+        match self {
+            SettingValue::Mode => format!("{:?}", config.mode),
+            SettingValue::Symbols => format!("{:?}", config.symbols),
+            SettingValue::SequenceStart => config.sequence_start.to_string(),
+            SettingValue::SequenceStep => config.sequence_step.to_string(),
+            SettingValue::SequenceOrder if config.sequence_descending => "Descending".to_string(),
+            SettingValue::SequenceOrder => "Ascending".to_string(),
+            SettingValue::Width => config.width.to_string(),
+            SettingValue::Height => config.height.to_string(),
+            SettingValue::AutoReturn if config.game_over_auto_return > 0.0 => {
+                format!("{} s", config.game_over_auto_return)
+            }
+            SettingValue::AutoReturn => "Off".to_string(),
+            SettingValue::Countdown if config.countdown > 0 => format!("{} s", config.countdown),
+            SettingValue::Countdown => "Off".to_string(),
+            SettingValue::MemoryPreview => format!("{} s", config.memory_preview),
+            SettingValue::TimerStart if config.start_timer_on_first_click => "First click".to_string(),
+            SettingValue::TimerStart => "Reveal".to_string(),
+            SettingValue::Hud(element) if element.enabled(config) => "Shown".to_string(),
+            SettingValue::Hud(_) => "Hidden".to_string(),
+            SettingValue::Shuffle => format!("{:?}", config.shuffle),
+            SettingValue::ShuffleAnimation => format!("{:.1} s", config.shuffle_animation),
+            SettingValue::Fixation => format!("{:?}", config.fixation),
+            SettingValue::GazeRadius => format!("{} px", config.gaze_radius),
+            SettingValue::Goal => format!("{:?}", config.goal),
+            SettingValue::TimeLimit => format!("{} s", config.time_limit),
+            SettingValue::MistakeLimit => config.mistake_limit.to_string(),
+            SettingValue::PenaltyPolicy => format!("{:?}", config.penalty_policy),
+            SettingValue::PenaltySeconds => format!("{} s", config.incorrect_penalty),
+            SettingValue::PenaltyPercent => format!("{} %", config.penalty_percent),
+            SettingValue::Distractors if config.distractors > 0 => config.distractors.to_string(),
+            SettingValue::Distractors => "Off".to_string(),
+            SettingValue::DistractorKind => format!("{:?}", config.distractor_kind),
+            SettingValue::PerturbRotation => perturbation_level(config.perturb_rotation),
+            SettingValue::PerturbSize => perturbation_level(config.perturb_size),
+            SettingValue::PerturbColor => perturbation_level(config.perturb_color),
+            SettingValue::PerturbMirror if config.perturb_mirror => "On".to_string(),
+            SettingValue::PerturbMirror => "Off".to_string(),
+            SettingValue::Seed => config.seed.map_or("Random".to_string(), |seed| seed.to_string()),
+            SettingValue::SaveReplays if config.save_replays => "On".to_string(),
+            SettingValue::SaveReplays => "Off".to_string(),
+            SettingValue::Ghost if config.ghost => "On".to_string(),
+            SettingValue::Ghost => "Off".to_string(),
+        }
+    }
+}
 
 // All actions that can be triggered from a button click
 #[derive(Component)]
 enum MenuButtonAction {
     Play,
+    DailyChallenge,
     Settings,
-    SettingsWidth,
-    SettingsHeight,
+    // Steps through the pages of the settings menu
+    SettingsPage(isize),
+    // Step applied to the setting when pressed
+    SettingsMode(isize),
+    SettingsSymbols(isize),
+    SettingsSequenceStart(i16),
+    SettingsSequenceStep(i16),
+    SettingsSequenceOrder,
+    SettingsWidth(isize),
+    SettingsHeight(isize),
+    SettingsAutoReturn(f32),
+    SettingsCountdown(isize),
+    SettingsMemoryPreview(isize),
+    // Switches between starting the timer on reveal and on the first correct click
+    SettingsTimerStart,
+    // Shows or hides an element of the in-game HUD
+    SettingsHud(HudElement),
+    SettingsShuffle(isize),
+    SettingsShuffleAnimation(f32),
+    SettingsFixation(isize),
+    SettingsGazeRadius(f32),
+    SettingsGoal(isize),
+    SettingsTimeLimit(i16),
+    SettingsMistakeLimit(i32),
+    SettingsPenaltyPolicy(isize),
+    SettingsPenaltySeconds(i8),
+    SettingsPenaltyPercent(i8),
+    SettingsDistractors(i8),
+    SettingsDistractorKind(isize),
+    SettingsPerturbRotation(i8),
+    SettingsPerturbSize(i8),
+    SettingsPerturbColor(i8),
+    SettingsPerturbMirror,
+    // Goes back to a random seed for every game, or fixes a new random seed
+    SettingsSeedRandom,
+    SettingsSeedNew,
+    SettingsSaveReplays,
+    SettingsGhost,
     Scoreboard,
+    // Steps through the score groups and pages of the scoreboard
+    ScoreboardBucket(isize),
+    ScoreboardPage(isize),
+    // Plays back the replay file of a score
+    WatchReplay(String),
     BackToMainMenu,
     Quit,
 }
 
-fn button_system(
+pub fn button_system(
     mut interaction_query: Query<
         (&Interaction, &mut BackgroundColor, Option<&SelectedOption>),
         (Changed<Interaction>, With<Button>),
@@ -88,14 +561,19 @@ fn button_system(
     }
 }
 
-fn menu_setup(mut menu_state: ResMut<NextState<MenuState>>) {
-    menu_state.set(MenuState::Main);
+fn menu_setup(
+    mut menu_state: ResMut<NextState<MenuState>>,
+    mut menu_screen: ResMut<MenuScreen>,
+) {
+    menu_state.set(**menu_screen);
+    *menu_screen = MenuScreen::default();
 }
 
 fn main_menu_setup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     config: Res<GameConfiguraiton>,
+    warnings: Res<ConfigWarnings>,
 ) {
     // Common style for all buttons on the screen
     let button_style = Style {
@@ -183,6 +661,21 @@ fn main_menu_setup(
                                 button_text_style.clone(),
                             ));
                         });
+                    parent
+                        .spawn((
+                            ButtonBundle {
+                                style: button_style.clone(),
+                                background_color: NORMAL_BUTTON.into(),
+                                ..default()
+                            },
+                            MenuButtonAction::DailyChallenge,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section(
+                                "Daily challenge",
+                                button_text_style.clone(),
+                            ));
+                        });
                     parent
                         .spawn((
                             ButtonBundle {
@@ -237,6 +730,23 @@ fn main_menu_setup(
                             });
                             parent.spawn(TextBundle::from_section("Quit", button_text_style));
                         });
+                    // Surface configuration problems instead of silently ignoring them
+                    for warning in warnings.iter() {
+                        parent.spawn(
+                            TextBundle::from_section(
+                                warning,
+                                TextStyle {
+                                    font_size: 20.0,
+                                    color: config.color_incorrect,
+                                    ..default()
+                                },
+                            )
+                            .with_style(Style {
+                                margin: UiRect::all(Val::Px(5.0)),
+                                ..default()
+                            }),
+                        );
+                    }
                 });
         });
 }
@@ -244,6 +754,8 @@ fn main_menu_setup(
 fn scoreboard_setup(
     mut commands: Commands,
     config: Res<GameConfiguraiton>,
+    mut scoreboard: ResMut<Scoreboard>,
+    mut view: ResMut<ScoreboardView>,
 ) {
     let button_style = Style {
         width: Val::Px(200.0),
@@ -258,7 +770,22 @@ fn scoreboard_setup(
         color: config.color_text,
         ..default()
     };
-    let scores = fs::read_to_string(&config.score_file_path).unwrap_or_default();
+    *scoreboard = match ScoreStore::from_config(&config).and_then(|store| store.load()) {
+        Ok(records) => Scoreboard::from_records(records),
+        Err(error) => Scoreboard {
+            error: Some(format!("Unable to load scores: {}", error)),
+            ..default()
+        },
+    };
+    // Start on the board size that is currently configured, if it has been played
+    *view = ScoreboardView {
+        bucket: scoreboard.buckets.iter()
+            .position(|(bucket, _)| {
+                bucket.width == Some(config.width) && bucket.height == Some(config.height)
+            })
+            .unwrap_or_default(),
+        page: 0,
+    };
     commands
         .spawn((
             NodeBundle {
@@ -273,22 +800,6 @@ fn scoreboard_setup(
             },
             OnScoreboardScreen,
         ))
-        .with_children(|parent| {
-            parent.spawn(
-                TextBundle::from_section(
-                    scores,
-                    TextStyle {
-                        font_size: 67.0,
-                        color: config.color_text,
-                        ..default()
-                    },
-                )
-                .with_style(Style {
-                    margin: UiRect::all(Val::Px(50.0)),
-                    ..default()
-                }),
-            );
-        })
         .with_children(|parent| {
             parent
                 .spawn(NodeBundle {
@@ -301,32 +812,239 @@ fn scoreboard_setup(
                     ..default()
                 })
                 .with_children(|parent| {
-                    for (action, text) in [
-                        (MenuButtonAction::BackToMainMenu, "Back"),
-                    ] {
-                        parent
-                            .spawn((
-                                ButtonBundle {
-                                    style: button_style.clone(),
-                                    background_color: NORMAL_BUTTON.into(),
-                                    ..default()
-                                },
-                                action,
-                            ))
-                            .with_children(|parent| {
-                                parent.spawn(TextBundle::from_section(
-                                    text,
-                                    button_text_style.clone(),
-                                ));
+                    // Filled by `scoreboard_table_system` whenever the view changes
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Column,
+                                align_items: AlignItems::Center,
+                                margin: UiRect::all(Val::Px(20.0)),
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        ScoreboardTable,
+                    ));
+                    parent
+                        .spawn((
+                            ButtonBundle {
+                                style: button_style,
+                                background_color: NORMAL_BUTTON.into(),
+                                ..default()
+                            },
+                            MenuButtonAction::BackToMainMenu,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section("Back", button_text_style));
+                        });
+                });
+        });
+}
+
+fn scoreboard_table_system(
+    mut commands: Commands,
+    config: Res<GameConfiguraiton>,
+    scoreboard: Res<Scoreboard>,
+    view: Res<ScoreboardView>,
+    table_query: Query<Entity, With<ScoreboardTable>>,
+) {
+    let Ok(table) = table_query.get_single() else {
+        return;
+    };
+    let step_button_style = Style {
+        width: Val::Px(65.0),
+        height: Val::Px(65.0),
+        margin: UiRect::all(Val::Px(10.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let title_text_style = TextStyle {
+        font_size: 33.0,
+        color: config.color_text,
+        ..default()
+    };
+    let cell_style = Style {
+        width: Val::Px(200.0),
+        justify_content: JustifyContent::Center,
+        margin: UiRect::all(Val::Px(3.0)),
+        ..default()
+    };
+    let cell_text_style = TextStyle {
+        font_size: 25.0,
+        color: config.color_text,
+        ..default()
+    };
+    let watch_button_style = Style {
+        width: Val::Px(100.0),
+        justify_content: JustifyContent::Center,
+        margin: UiRect::all(Val::Px(3.0)),
+        ..default()
+    };
+
+    commands.entity(table).despawn_descendants();
+    commands.entity(table).with_children(|parent| {
+        if let Some(error) = &scoreboard.error {
+            parent.spawn(TextBundle::from_section(
+                error,
+                TextStyle {
+                    color: config.color_incorrect,
+                    ..title_text_style
+                },
+            ));
+            return;
+        }
+        let Some((bucket, records)) = scoreboard.buckets.get(view.bucket) else {
+            parent.spawn(TextBundle::from_section("No scores yet", title_text_style));
+            return;
+        };
+        let pages = records.len().div_ceil(SCOREBOARD_PAGE_SIZE).max(1);
+
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                ..default()
+            })
+            .with_children(|parent| {
+                spawn_step_button(
+                    parent,
+                    MenuButtonAction::ScoreboardBucket(-1),
+                    "<",
+                    &step_button_style,
+                    &title_text_style,
+                );
+                parent.spawn(
+                    TextBundle::from_section(bucket.to_string(), title_text_style.clone())
+                        .with_style(Style {
+                            width: Val::Px(500.0),
+                            justify_content: JustifyContent::Center,
+                            ..default()
+                        }),
+                );
+                spawn_step_button(
+                    parent,
+                    MenuButtonAction::ScoreboardBucket(1),
+                    ">",
+                    &step_button_style,
+                    &title_text_style,
+                );
+            });
+
+        if bucket.daily {
+            let streaks = daily::streaks(records.iter().filter_map(|record| record.daily), daily::today());
+            parent.spawn(TextBundle::from_section(
+                format!("Streak: {} days, best {} days", streaks.current, streaks.best),
+                cell_text_style.clone(),
+            ));
+        }
+
+        // The daily history shows which table each day had instead of a rank
+        let header = if bucket.daily {
+            ["Day", "Score", "Mistakes", "Table"]
+        } else {
+            ["#", "Score", "Mistakes", "Date"]
+        }
+        .map(str::to_string);
+        let rows = records.iter()
+            .enumerate()
+            .skip(view.page * SCOREBOARD_PAGE_SIZE)
+            .take(SCOREBOARD_PAGE_SIZE)
+            .map(|(rank, record)| {
+                let mistakes = record.mistakes.map_or("-".to_string(), |mistakes| mistakes.to_string());
+                if let Some(date) = record.daily {
+                    let table = match (record.width, record.height, record.mode) {
+                        (Some(width), Some(height), Some(mode)) => format!("{}x{} {:?}", width, height, mode),
+                        _ => "-".to_string(),
+                    };
+                    return ([date.to_string(), record.format_score(), mistakes, table], false, record.replay.clone());
+                }
+                let row = [
+                    (rank + 1).to_string(),
+                    record.format_score(),
+                    mistakes,
+                    record.timestamp.map_or("-".to_string(), |timestamp| {
+                        timestamp.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string()
+                    }),
+                ];
+                // Records are sorted, so the first one is the personal best
+                (row, rank == 0, record.replay.clone())
+            });
+        for (row, personal_best, replay) in std::iter::once((header, false, None)).chain(rows) {
+            parent
+                .spawn(NodeBundle {
+                    background_color: if personal_best {
+                        config.color_correct.into()
+                    } else {
+                        Color::NONE.into()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for cell in row {
+                        parent.spawn(
+                            TextBundle::from_section(cell, cell_text_style.clone())
+                                .with_style(cell_style.clone()),
+                        );
+                    }
+                    // Same width with or without a replay so the columns stay aligned
+                    match replay {
+                        Some(replay) => spawn_step_button(
+                            parent,
+                            MenuButtonAction::WatchReplay(replay),
+                            "Watch",
+                            &watch_button_style,
+                            &cell_text_style,
+                        ),
+                        None => {
+                            parent.spawn(NodeBundle {
+                                style: watch_button_style.clone(),
+                                ..default()
                             });
+                        }
                     }
                 });
-        });
+        }
+
+        if pages > 1 {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    spawn_step_button(
+                        parent,
+                        MenuButtonAction::ScoreboardPage(-1),
+                        "<",
+                        &step_button_style,
+                        &title_text_style,
+                    );
+                    parent.spawn(TextBundle::from_section(
+                        format!("Page {}/{}", view.page + 1, pages),
+                        title_text_style.clone(),
+                    ));
+                    spawn_step_button(
+                        parent,
+                        MenuButtonAction::ScoreboardPage(1),
+                        ">",
+                        &step_button_style,
+                        &title_text_style,
+                    );
+                });
+        }
+    });
 }
 
 fn settings_menu_setup(
     mut commands: Commands,
     config: Res<GameConfiguraiton>,
+    mut settings_page: ResMut<SettingsPage>,
 ) {
     let button_style = Style {
         width: Val::Px(200.0),
@@ -336,6 +1054,10 @@ fn settings_menu_setup(
         align_items: AlignItems::Center,
         ..default()
     };
+    let step_button_style = Style {
+        width: Val::Px(65.0),
+        ..button_style.clone()
+    };
 
     let button_text_style = TextStyle {
         font_size: 33.0,
@@ -343,6 +1065,7 @@ fn settings_menu_setup(
         ..default()
     };
 
+    **settings_page = 0;
     commands
         .spawn((
             NodeBundle {
@@ -369,39 +1092,202 @@ fn settings_menu_setup(
                     ..default()
                 })
                 .with_children(|parent| {
-                    for (action, text) in [
-                        (MenuButtonAction::SettingsWidth, "Width"),
-                        (MenuButtonAction::SettingsHeight, "Height"),
-                        (MenuButtonAction::BackToMainMenu, "Back"),
-                    ] {
-                        parent
-                            .spawn((
-                                ButtonBundle {
-                                    style: button_style.clone(),
-                                    background_color: NORMAL_BUTTON.into(),
-                                    ..default()
-                                },
-                                action,
-                            ))
-                            .with_children(|parent| {
-                                parent.spawn(TextBundle::from_section(
-                                    text,
-                                    button_text_style.clone(),
-                                ));
-                            });
-                    }
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            spawn_step_button(
+                                parent,
+                                MenuButtonAction::SettingsPage(-1),
+                                "<",
+                                &step_button_style,
+                                &button_text_style,
+                            );
+                            parent.spawn((
+                                TextBundle::from_section("", button_text_style.clone())
+                                    .with_style(Style {
+                                        width: Val::Px(300.0),
+                                        justify_content: JustifyContent::Center,
+                                        ..default()
+                                    }),
+                                SettingsPageTitle,
+                            ));
+                            spawn_step_button(
+                                parent,
+                                MenuButtonAction::SettingsPage(1),
+                                ">",
+                                &step_button_style,
+                                &button_text_style,
+                            );
+                        });
+                    // Filled by `settings_rows_system` whenever the page changes
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Column,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        SettingsRows,
+                    ));
+                    parent
+                        .spawn((
+                            ButtonBundle {
+                                style: button_style,
+                                background_color: NORMAL_BUTTON.into(),
+                                ..default()
+                            },
+                            MenuButtonAction::BackToMainMenu,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section("Back", button_text_style));
+                        });
                 });
         });
 }
 
+fn settings_rows_system(
+    mut commands: Commands,
+    config: Res<GameConfiguraiton>,
+    settings_page: Res<SettingsPage>,
+    rows_query: Query<Entity, With<SettingsRows>>,
+    mut title_query: Query<&mut Text, With<SettingsPageTitle>>,
+) {
+    let Ok(rows) = rows_query.get_single() else {
+        return;
+    };
+    let step_button_style = Style {
+        width: Val::Px(65.0),
+        height: Val::Px(65.0),
+        margin: UiRect::all(Val::Px(10.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let label_style = Style {
+        width: Val::Px(250.0),
+        ..default()
+    };
+    // Long values like seeds widen their row instead of overflowing
+    let value_style = Style {
+        min_width: Val::Px(200.0),
+        justify_content: JustifyContent::Center,
+        ..default()
+    };
+    let button_text_style = TextStyle {
+        font_size: 33.0,
+        color: config.color_text,
+        ..default()
+    };
+
+    let (title, settings) = SETTINGS_PAGES[**settings_page];
+    for mut text in &mut title_query {
+        text.sections[0].value = title.to_string();
+    }
+    commands.entity(rows).despawn_descendants();
+    commands.entity(rows).with_children(|parent| {
+        // One row per setting: label, decrement button, current value, increment button
+        for &setting in settings {
+            let (decrement, increment) = setting.steps();
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(
+                        TextBundle::from_section(setting.label(), button_text_style.clone())
+                            .with_style(label_style.clone()),
+                    );
+                    spawn_step_button(
+                        parent,
+                        decrement,
+                        "-",
+                        &step_button_style,
+                        &button_text_style,
+                    );
+                    parent.spawn((
+                        TextBundle::from_section(setting.value(&config), button_text_style.clone())
+                            .with_style(value_style.clone()),
+                        setting,
+                    ));
+                    spawn_step_button(
+                        parent,
+                        increment,
+                        "+",
+                        &step_button_style,
+                        &button_text_style,
+                    );
+                });
+        }
+    });
+}
+
+fn save_settings(
+    config: Res<GameConfiguraiton>,
+    mut warnings: ResMut<ConfigWarnings>,
+) {
+    if let Err(error) = save_configuration(&config) {
+        let warning = format!("Unable to save settings: {}", error);
+        warn!("{}", warning);
+        warnings.push(warning);
+    }
+}
+
+fn spawn_step_button(
+    parent: &mut ChildBuilder,
+    action: MenuButtonAction,
+    text: &str,
+    style: &Style,
+    text_style: &TextStyle,
+) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: style.clone(),
+                background_color: NORMAL_BUTTON.into(),
+                ..default()
+            },
+            action,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(text, text_style.clone()));
+        });
+}
+
+// Keeps the displayed setting values in sync with the configuration
+fn setting_value_system(
+    config: Res<GameConfiguraiton>,
+    mut query: Query<(&mut Text, &SettingValue)>,
+) {
+    for (mut text, setting) in &mut query {
+        text.sections[0].value = setting.value(&config);
+    }
+}
+
 fn menu_action(
     interaction_query: Query<
         (&Interaction, &MenuButtonAction),
         (Changed<Interaction>, With<Button>),
     >,
+    mut commands: Commands,
     mut app_exit_events: EventWriter<AppExit>,
     mut menu_state: ResMut<NextState<MenuState>>,
     mut game_state: ResMut<NextState<GameState>>,
+    mut config: ResMut<GameConfiguraiton>,
+    mut scoreboard: ResMut<Scoreboard>,
+    mut view: ResMut<ScoreboardView>,
+    mut settings_page: ResMut<SettingsPage>,
 ) {
     for (interaction, menu_button_action) in &interaction_query {
         if *interaction == Interaction::Pressed {
@@ -413,15 +1299,235 @@ fn menu_action(
                     game_state.set(GameState::Game);
                     menu_state.set(MenuState::Disabled);
                 }
+                MenuButtonAction::DailyChallenge => {
+                    commands.insert_resource(DailyChallenge::start(&mut config));
+                    game_state.set(GameState::Game);
+                    menu_state.set(MenuState::Disabled);
+                }
                 MenuButtonAction::Scoreboard => {
                     menu_state.set(MenuState::Scoreboard);
                 }
+                MenuButtonAction::ScoreboardBucket(step) => {
+                    let buckets = scoreboard.buckets.len().max(1);
+                    view.bucket = (view.bucket as isize + step).rem_euclid(buckets as isize) as usize;
+                    view.page = 0;
+                }
+                MenuButtonAction::ScoreboardPage(step) => {
+                    let records = scoreboard.buckets.get(view.bucket).map_or(0, |(_, records)| records.len());
+                    let pages = records.div_ceil(SCOREBOARD_PAGE_SIZE).max(1);
+                    view.page = view.page.saturating_add_signed(*step).min(pages - 1);
+                }
+                MenuButtonAction::WatchReplay(name) => {
+                    match ScoreStore::from_config(&config).and_then(|store| store.load_replay(name)) {
+                        Ok(replay) => {
+                            commands.insert_resource(Playback::new(replay, MenuState::Scoreboard));
+                            game_state.set(GameState::Replay);
+                            menu_state.set(MenuState::Disabled);
+                        }
+                        Err(error) => {
+                            warn!("Unable to load replay: {}", error);
+                            scoreboard.error = Some(format!("Unable to load replay: {}", error));
+                            view.set_changed();
+                        }
+                    }
+                }
                 MenuButtonAction::Settings => menu_state.set(MenuState::Settings),
-                // TODO add settings actions
-                MenuButtonAction::SettingsHeight => {}
-                MenuButtonAction::SettingsWidth => {}
+                MenuButtonAction::SettingsPage(step) => {
+                    let pages = SETTINGS_PAGES.len() as isize;
+                    **settings_page = (**settings_page as isize + step).rem_euclid(pages) as usize;
+                }
+                MenuButtonAction::SettingsMode(step) => {
+                    config.mode = cycle(&GameMode::ALL, config.mode, *step);
+                }
+                MenuButtonAction::SettingsSymbols(step) => {
+                    config.symbols = cycle(&SymbolSet::ALL, config.symbols, *step);
+                }
+                MenuButtonAction::SettingsSequenceStart(step) => {
+                    config.sequence_start = config.sequence_start
+                        .saturating_add_signed(*step)
+                        .clamp(1, MAX_SEQUENCE_START);
+                }
+                MenuButtonAction::SettingsSequenceStep(step) => {
+                    config.sequence_step = config.sequence_step
+                        .saturating_add_signed(*step)
+                        .clamp(1, MAX_SEQUENCE_STEP);
+                }
+                MenuButtonAction::SettingsSequenceOrder => {
+                    config.sequence_descending = !config.sequence_descending;
+                }
+                MenuButtonAction::SettingsWidth(step) => {
+                    config.width = step_grid_size(config.width, *step);
+                }
+                MenuButtonAction::SettingsHeight(step) => {
+                    config.height = step_grid_size(config.height, *step);
+                }
+                MenuButtonAction::SettingsAutoReturn(step) => {
+                    config.game_over_auto_return =
+                        (config.game_over_auto_return + step).clamp(0.0, MAX_AUTO_RETURN);
+                }
+                MenuButtonAction::SettingsCountdown(step) => {
+                    config.countdown = config.countdown
+                        .saturating_add_signed(*step as i8)
+                        .min(MAX_COUNTDOWN);
+                }
+                MenuButtonAction::SettingsMemoryPreview(step) => {
+                    config.memory_preview = config.memory_preview
+                        .saturating_add_signed(*step as i8)
+                        .clamp(1, MAX_MEMORY_PREVIEW);
+                }
+                MenuButtonAction::SettingsTimerStart => {
+                    config.start_timer_on_first_click = !config.start_timer_on_first_click;
+                }
+                MenuButtonAction::SettingsHud(element) => element.toggle(&mut config),
+                MenuButtonAction::SettingsShuffle(step) => {
+                    config.shuffle = cycle(&ShuffleMode::ALL, config.shuffle, *step);
+                }
+                MenuButtonAction::SettingsShuffleAnimation(step) => {
+                    // Rounded to tenths so repeated steps do not accumulate float errors
+                    let animation = ((config.shuffle_animation + step) * 10.0).round() / 10.0;
+                    config.shuffle_animation = animation.clamp(0.0, MAX_SHUFFLE_ANIMATION);
+                }
+                MenuButtonAction::SettingsFixation(step) => {
+                    config.fixation = cycle(&FixationMode::ALL, config.fixation, *step);
+                }
+                MenuButtonAction::SettingsGazeRadius(step) => {
+                    config.gaze_radius = (config.gaze_radius + step).clamp(MIN_GAZE_RADIUS, MAX_GAZE_RADIUS);
+                }
+                MenuButtonAction::SettingsGoal(step) => {
+                    config.goal = cycle(&RunGoal::ALL, config.goal, *step);
+                }
+                MenuButtonAction::SettingsTimeLimit(step) => {
+                    config.time_limit = config.time_limit
+                        .saturating_add_signed(*step)
+                        .clamp(MIN_TIME_LIMIT, MAX_TIME_LIMIT);
+                }
+                MenuButtonAction::SettingsMistakeLimit(step) => {
+                    config.mistake_limit = config.mistake_limit
+                        .saturating_add_signed(*step)
+                        .clamp(1, MAX_MISTAKE_LIMIT);
+                }
+                MenuButtonAction::SettingsPenaltyPolicy(step) => {
+                    config.penalty_policy = cycle(&PenaltyPolicy::ALL, config.penalty_policy, *step);
+                }
+                MenuButtonAction::SettingsPenaltySeconds(step) => {
+                    config.incorrect_penalty = config.incorrect_penalty
+                        .saturating_add_signed(*step)
+                        .min(MAX_INCORRECT_PENALTY);
+                }
+                MenuButtonAction::SettingsPenaltyPercent(step) => {
+                    config.penalty_percent = config.penalty_percent
+                        .saturating_add_signed(*step)
+                        .clamp(1, MAX_PENALTY_PERCENT);
+                }
+                MenuButtonAction::SettingsDistractors(step) => {
+                    config.distractors = config.distractors
+                        .saturating_add_signed(*step)
+                        .min(MAX_DISTRACTORS);
+                }
+                MenuButtonAction::SettingsDistractorKind(step) => {
+                    config.distractor_kind = cycle(&DistractorKind::ALL, config.distractor_kind, *step);
+                }
+                MenuButtonAction::SettingsPerturbRotation(step) => {
+                    config.perturb_rotation = step_perturbation(config.perturb_rotation, *step);
+                }
+                MenuButtonAction::SettingsPerturbSize(step) => {
+                    config.perturb_size = step_perturbation(config.perturb_size, *step);
+                }
+                MenuButtonAction::SettingsPerturbColor(step) => {
+                    config.perturb_color = step_perturbation(config.perturb_color, *step);
+                }
+                MenuButtonAction::SettingsPerturbMirror => {
+                    config.perturb_mirror = !config.perturb_mirror;
+                }
+                MenuButtonAction::SettingsSeedRandom => config.seed = None,
+                MenuButtonAction::SettingsSeedNew => config.seed = Some(thread_rng().gen()),
+                MenuButtonAction::SettingsSaveReplays => {
+                    config.save_replays = !config.save_replays;
+                }
+                MenuButtonAction::SettingsGhost => config.ghost = !config.ghost,
                 MenuButtonAction::BackToMainMenu => menu_state.set(MenuState::Main),
             }
         }
     }
 }
+
+const DIGIT_KEYS: [KeyCode; 10] = [
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+const NUMPAD_KEYS: [KeyCode; 10] = [
+    KeyCode::Numpad0,
+    KeyCode::Numpad1,
+    KeyCode::Numpad2,
+    KeyCode::Numpad3,
+    KeyCode::Numpad4,
+    KeyCode::Numpad5,
+    KeyCode::Numpad6,
+    KeyCode::Numpad7,
+    KeyCode::Numpad8,
+    KeyCode::Numpad9,
+];
+
+// Seeds are too large to step through, so digits typed on the page of the seed are appended to it
+fn seed_input(
+    keys: Res<ButtonInput<KeyCode>>,
+    settings_page: Res<SettingsPage>,
+    mut config: ResMut<GameConfiguraiton>,
+) {
+    let (_, settings) = SETTINGS_PAGES[**settings_page];
+    if !settings.iter().any(|setting| matches!(setting, SettingValue::Seed)) {
+        return;
+    }
+    let mut seed = config.seed;
+    for key in keys.get_just_pressed() {
+        if *key == KeyCode::Backspace {
+            seed = seed.map(|seed| seed / 10).filter(|seed| *seed > 0);
+        } else if *key == KeyCode::Delete {
+            seed = None;
+        } else if let Some(digit) = DIGIT_KEYS.iter()
+            .position(|digit_key| digit_key == key)
+            .or_else(|| NUMPAD_KEYS.iter().position(|numpad_key| numpad_key == key))
+        {
+            // Digits that would overflow the seed are ignored
+            let appended = seed.unwrap_or_default()
+                .checked_mul(10)
+                .and_then(|seed| seed.checked_add(digit as u64));
+            seed = appended.or(seed);
+        }
+    }
+    // Only touch the configuration on a change, every change is saved to disk
+    if seed != config.seed {
+        config.seed = seed;
+    }
+}
+
+// Value `step` places away from `current` in `values`, wrapping around at both ends
+fn cycle<T: Copy + PartialEq>(values: &[T], current: T, step: isize) -> T {
+    let index = values.iter().position(|value| *value == current).unwrap_or_default();
+    values[(index as isize + step).rem_euclid(values.len() as isize) as usize]
+}
+
+fn step_grid_size(size: usize, step: isize) -> usize {
+    size.saturating_add_signed(step).clamp(MIN_GRID_SIZE, MAX_GRID_SIZE)
+}
+
+fn step_perturbation(level: u8, step: i8) -> u8 {
+    level.saturating_add_signed(step).min(MAX_PERTURBATION)
+}
+
+fn perturbation_level(level: u8) -> String {
+    if level > 0 {
+        format!("{}/{}", level, MAX_PERTURBATION)
+    } else {
+        "Off".to_string()
+    }
+}
@@ -0,0 +1,644 @@
+use bevy::{
+    prelude::*,
+    ecs::world::Command,
+    time::Stopwatch,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use super::board::{ClickOutcome, SchulteBoard};
+use super::config::{validate_configuration, ConfigWarnings};
+use super::game::{
+    animate_reshuffle,
+    blink_system,
+    blink_tile,
+    move_tiles,
+    spawn_table,
+    LabelRng,
+    TileGrid,
+    TileSlot,
+};
+use super::menu::{button_system, MenuScreen, MenuState, NORMAL_BUTTON};
+use super::{
+    despawn_screen,
+    GamePhase,
+    GameState,
+    GameConfiguraiton,
+};
+
+// Cursor positions recorded per second of play
+const CURSOR_SAMPLE_RATE: f32 = 30.0;
+const PLAYBACK_SPEEDS: [f32; 6] = [0.5, 1.0, 1.5, 2.0, 3.0, 4.0];
+const CURSOR_SIZE: f32 = 16.0;
+
+pub fn replay_plugin(app: &mut App) {
+    app
+        .add_systems(Update, record_run.run_if(in_state(GamePhase::Playing)))
+        .add_systems(OnEnter(GameState::Replay), playback_setup)
+        .add_systems(
+            Update,
+            (
+                playback_action,
+                playback_shortcuts,
+                button_system,
+                advance_playback.after(playback_action).after(playback_shortcuts),
+                blink_system,
+                move_tiles,
+                playback_cursor.after(advance_playback),
+                playback_hud.after(advance_playback),
+            )
+                .run_if(in_state(GameState::Replay)),
+        )
+        .add_systems(OnExit(GameState::Replay), (despawn_screen::<OnReplayScreen>, playback_cleanup));
+}
+
+// Everything needed to watch a run again, the board is generated from the seed and the clicks applied to it
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub timestamp: DateTime<Utc>,
+    pub seed: u64,
+    pub config: GameConfiguraiton,
+    // Seconds of play recorded, without pauses, countdowns and memory previews
+    pub duration: f32,
+    pub clicks: Vec<ReplayClick>,
+    pub cursor: Vec<CursorSample>,
+}
+
+// Press on a tile, including the ones that did not count
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct ReplayClick {
+    // Seconds on the replay clock
+    pub time: f32,
+    // Seconds on the game stopwatch, which penalties and time limits are based on
+    pub elapsed: f32,
+    pub slot: usize,
+}
+
+impl ReplayClick {
+    // Loaded replays are validated, a time that is not a valid duration only comes from a replay built by hand
+    pub fn elapsed(&self) -> Duration {
+        Duration::try_from_secs_f32(self.elapsed).unwrap_or_default()
+    }
+}
+
+impl Replay {
+    // Checks everything that would make the playback panic or differ from the recorded run
+    pub fn validate(&self) -> Result<(), String> {
+        let mut config = self.config.clone();
+        let mut warnings = ConfigWarnings::default();
+        validate_configuration(&mut config, &mut warnings);
+        if let Some(warning) = warnings.first() {
+            return Err(format!("invalid configuration: {}", warning));
+        }
+        if !(self.duration.is_finite() && self.duration >= 0.0) {
+            return Err(format!("invalid duration {}", self.duration));
+        }
+        for (index, click) in self.clicks.iter().enumerate() {
+            if !(click.time.is_finite() && click.time >= 0.0) {
+                return Err(format!("invalid time {} of click {}", click.time, index));
+            }
+            if let Err(error) = Duration::try_from_secs_f32(click.elapsed) {
+                return Err(format!("invalid elapsed time {} of click {}: {}", click.elapsed, index, error));
+            }
+        }
+        let finite = |sample: &&CursorSample| sample.time.is_finite() && sample.x.is_finite() && sample.y.is_finite();
+        if let Some(sample) = self.cursor.iter().find(|sample| !finite(sample)) {
+            return Err(format!("invalid cursor sample at {}", sample.time));
+        }
+        Ok(())
+    }
+}
+
+// What a recorded click did to the board
+pub struct AppliedClick {
+    pub outcome: ClickOutcome,
+    // New slot of the tile in each old slot, after a correct click
+    pub moves: Option<Vec<usize>>,
+    // The click finished a table and the next one was dealt
+    pub next_table: bool,
+}
+
+// Applies a recorded click the way the game does: correct clicks reshuffle the table
+// and a finished table makes way for the next one unless the run is over
+pub fn apply_click(board: &mut SchulteBoard, click: &ReplayClick) -> AppliedClick {
+    let elapsed = click.elapsed();
+    let outcome = board.click(click.slot, elapsed);
+    if !matches!(outcome, ClickOutcome::Correct(_)) {
+        return AppliedClick {
+            outcome,
+            moves: None,
+            next_table: false,
+        };
+    }
+    let moves = board.reshuffle();
+    let next_table = board.is_complete() && !board.is_over(elapsed);
+    if next_table {
+        board.next_table();
+    }
+    AppliedClick {
+        outcome,
+        moves: Some(moves),
+        next_table,
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct CursorSample {
+    pub time: f32,
+    // Logical pixels from the top left corner of the table
+    pub x: f32,
+    pub y: f32,
+}
+
+// Replay of the run being played, its clock only runs while the table can be played
+#[derive(Resource)]
+pub struct RunRecording {
+    pub replay: Replay,
+    clock: Stopwatch,
+    next_sample: f32,
+}
+
+impl RunRecording {
+    pub fn new(seed: u64, config: &GameConfiguraiton) -> Self {
+        RunRecording {
+            replay: Replay {
+                timestamp: Utc::now(),
+                seed,
+                config: config.clone(),
+                duration: 0.0,
+                clicks: Vec::new(),
+                cursor: Vec::new(),
+            },
+            clock: Stopwatch::new(),
+            next_sample: 0.0,
+        }
+    }
+
+    pub fn click(&mut self, slot: usize, elapsed: Duration) {
+        self.replay.clicks.push(ReplayClick {
+            time: self.clock.elapsed_secs(),
+            elapsed: elapsed.as_secs_f32(),
+            slot,
+        });
+    }
+}
+
+fn record_run(
+    time: Res<Time>,
+    mut recording: ResMut<RunRecording>,
+    grid_query: Query<(&Node, &GlobalTransform), With<TileGrid>>,
+    window_query: Query<&Window>,
+) {
+    recording.clock.tick(time.delta());
+    let now = recording.clock.elapsed_secs();
+    recording.replay.duration = now;
+    if now < recording.next_sample {
+        return;
+    }
+    recording.next_sample = now + 1.0 / CURSOR_SAMPLE_RATE;
+    let cursor = window_query.get_single().ok().and_then(Window::cursor_position);
+    let Some(((grid, grid_transform), cursor)) = grid_query.get_single().ok().zip(cursor) else {
+        return;
+    };
+    let position = cursor - (grid_transform.translation().truncate() - grid.size() / 2.0);
+    recording.replay.cursor.push(CursorSample {
+        time: now,
+        x: position.x,
+        y: position.y,
+    });
+}
+
+#[derive(Component)]
+struct OnReplayScreen;
+
+#[derive(Component)]
+struct ReplayCursor;
+
+#[derive(Component)]
+struct ReplayText;
+
+#[derive(Component, Clone, Copy)]
+enum ReplayButtonAction {
+    Slower,
+    Pause,
+    Faster,
+    Back,
+}
+
+// Replay being watched, the configuration of the player is swapped for the recorded one meanwhile
+#[derive(Resource)]
+pub struct Playback {
+    replay: Replay,
+    board: SchulteBoard,
+    label_rng: LabelRng,
+    return_to: MenuState,
+    speed: usize,
+    paused: bool,
+    clock: f32,
+    next_click: usize,
+    player_config: Option<GameConfiguraiton>,
+}
+
+impl Playback {
+    pub fn new(replay: Replay, return_to: MenuState) -> Self {
+        let board = SchulteBoard::new(replay.config.board_rules(), replay.seed);
+        Playback {
+            label_rng: LabelRng::new(replay.seed),
+            replay,
+            board,
+            return_to,
+            speed: 1,
+            paused: false,
+            clock: 0.0,
+            next_click: 0,
+            player_config: None,
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.clock >= self.replay.duration
+    }
+}
+
+fn playback_setup(
+    mut commands: Commands,
+    mut playback: ResMut<Playback>,
+    mut config: ResMut<GameConfiguraiton>,
+    asset_server: Res<AssetServer>,
+) {
+    let playback = &mut *playback;
+    playback.player_config = Some(std::mem::replace(&mut *config, playback.replay.config.clone()));
+    let symbol_font = asset_server.load(&config.symbol_font);
+    let text_style = TextStyle {
+        font_size: 25.0,
+        color: config.color_text,
+        ..default()
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    height: Val::Percent(100.0),
+                    width: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            OnReplayScreen,
+        ))
+        .with_children(|parent| {
+            // Every label is shown, the replay is about where the player looked for them
+            let grid = spawn_table(
+                parent,
+                &playback.board,
+                &mut playback.label_rng,
+                &config,
+                &symbol_font,
+                Visibility::Inherited,
+            );
+            parent.add_command(SpawnCursor(grid));
+            parent.spawn((
+                TextBundle::from_section("", text_style.clone()).with_style(Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(20.0),
+                    left: Val::Px(20.0),
+                    ..default()
+                }),
+                ReplayText,
+            ));
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        bottom: Val::Px(20.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for (action, text) in [
+                        (ReplayButtonAction::Slower, "Slower (Left)"),
+                        (ReplayButtonAction::Pause, "Pause (Space)"),
+                        (ReplayButtonAction::Faster, "Faster (Right)"),
+                        (ReplayButtonAction::Back, "Back (Esc)"),
+                    ] {
+                        parent
+                            .spawn((
+                                ButtonBundle {
+                                    style: Style {
+                                        margin: UiRect::all(Val::Px(10.0)),
+                                        padding: UiRect::all(Val::Px(10.0)),
+                                        ..default()
+                                    },
+                                    background_color: NORMAL_BUTTON.into(),
+                                    ..default()
+                                },
+                                action,
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(text, text_style.clone()));
+                            });
+                    }
+                });
+        });
+}
+
+// The cursor is a child of the table so it can use the recorded table coordinates
+struct SpawnCursor(Entity);
+
+impl Command for SpawnCursor {
+    fn apply(self, world: &mut World) {
+        let color = world.resource::<GameConfiguraiton>().color_fixation;
+        world.entity_mut(self.0).with_children(|grid| {
+            grid.spawn((
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        width: Val::Px(CURSOR_SIZE),
+                        height: Val::Px(CURSOR_SIZE),
+                        ..default()
+                    },
+                    background_color: color.with_alpha(0.7).into(),
+                    border_radius: BorderRadius::MAX,
+                    visibility: Visibility::Hidden,
+                    z_index: ZIndex::Local(2),
+                    ..default()
+                },
+                ReplayCursor,
+            ));
+        });
+    }
+}
+
+fn playback_action(
+    interaction_query: Query<
+        (&Interaction, &ReplayButtonAction),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut playback: ResMut<Playback>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut menu_screen: ResMut<MenuScreen>,
+) {
+    for (interaction, action) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            apply_action(*action, &mut playback, &mut game_state, &mut menu_screen);
+        }
+    }
+}
+
+fn playback_shortcuts(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut playback: ResMut<Playback>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut menu_screen: ResMut<MenuScreen>,
+) {
+    let action = if keys.any_just_pressed([KeyCode::ArrowLeft, KeyCode::Minus]) {
+        ReplayButtonAction::Slower
+    } else if keys.any_just_pressed([KeyCode::ArrowRight, KeyCode::Equal]) {
+        ReplayButtonAction::Faster
+    } else if keys.any_just_pressed([KeyCode::Space, KeyCode::KeyP]) {
+        ReplayButtonAction::Pause
+    } else if keys.just_pressed(KeyCode::Escape) {
+        ReplayButtonAction::Back
+    } else {
+        return;
+    };
+    apply_action(action, &mut playback, &mut game_state, &mut menu_screen);
+}
+
+fn apply_action(
+    action: ReplayButtonAction,
+    playback: &mut Playback,
+    game_state: &mut NextState<GameState>,
+    menu_screen: &mut MenuScreen,
+) {
+    match action {
+        ReplayButtonAction::Slower => playback.speed = playback.speed.saturating_sub(1),
+        ReplayButtonAction::Faster => playback.speed = (playback.speed + 1).min(PLAYBACK_SPEEDS.len() - 1),
+        ReplayButtonAction::Pause => playback.paused = !playback.paused,
+        ReplayButtonAction::Back => {
+            **menu_screen = playback.return_to;
+            game_state.set(GameState::Menu);
+        }
+    }
+}
+
+// Applies the recorded clicks to the board, the same way the game does when they happen
+fn advance_playback(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut playback: ResMut<Playback>,
+    mut tile_query: Query<(Entity, &mut TileSlot, &Style, &mut BackgroundColor)>,
+    grid_query: Query<Entity, With<TileGrid>>,
+    screen_query: Query<Entity, With<OnReplayScreen>>,
+    asset_server: Res<AssetServer>,
+    config: Res<GameConfiguraiton>,
+) {
+    let playback = &mut *playback;
+    if playback.paused || playback.finished() {
+        return;
+    }
+    playback.clock = (playback.clock + time.delta_seconds() * PLAYBACK_SPEEDS[playback.speed])
+        .min(playback.replay.duration);
+    while let Some(click) = playback.replay.clicks.get(playback.next_click).copied() {
+        if click.time > playback.clock {
+            break;
+        }
+        playback.next_click += 1;
+        let applied = apply_click(&mut playback.board, &click);
+        let correct = match applied.outcome {
+            ClickOutcome::Correct(_) => true,
+            ClickOutcome::Mistake { .. } => false,
+            ClickOutcome::Ignored => continue,
+        };
+        if let Some((entity, _, _, mut background)) = tile_query.iter_mut()
+            .find(|(_, slot, _, _)| ***slot == click.slot)
+        {
+            blink_tile(&mut commands, entity, &mut background, correct, &config);
+        }
+        if let Some(moves) = applied.moves {
+            animate_reshuffle(
+                &mut commands,
+                tile_query.iter_mut().map(|(entity, slot, style, _)| (entity, slot, style)),
+                &moves,
+                &config,
+            );
+        }
+        if applied.next_table {
+            // The next table only exists once the commands are applied, so its clicks wait a frame
+            for grid in &grid_query {
+                commands.entity(grid).despawn_recursive();
+            }
+            let symbol_font = asset_server.load(&config.symbol_font);
+            if let Ok(screen) = screen_query.get_single() {
+                commands.entity(screen).with_children(|parent| {
+                    let grid = spawn_table(
+                        parent,
+                        &playback.board,
+                        &mut playback.label_rng,
+                        &config,
+                        &symbol_font,
+                        Visibility::Inherited,
+                    );
+                    parent.add_command(SpawnCursor(grid));
+                });
+            }
+            break;
+        }
+    }
+}
+
+// Moves the cursor between the recorded samples around the playback clock
+fn playback_cursor(
+    playback: Res<Playback>,
+    mut cursor_query: Query<(&mut Style, &mut Visibility), With<ReplayCursor>>,
+) {
+    let samples = &playback.replay.cursor;
+    let next = samples.partition_point(|sample| sample.time <= playback.clock);
+    let position = match (next.checked_sub(1).map(|index| samples[index]), samples.get(next).copied()) {
+        (Some(previous), Some(next)) => {
+            let t = (playback.clock - previous.time) / (next.time - previous.time).max(f32::EPSILON);
+            Vec2::new(previous.x, previous.y).lerp(Vec2::new(next.x, next.y), t)
+        }
+        (Some(sample), None) | (None, Some(sample)) => Vec2::new(sample.x, sample.y),
+        (None, None) => {
+            for (_, mut visibility) in &mut cursor_query {
+                *visibility = Visibility::Hidden;
+            }
+            return;
+        }
+    };
+    for (mut style, mut visibility) in &mut cursor_query {
+        style.left = Val::Px(position.x - CURSOR_SIZE / 2.0);
+        style.top = Val::Px(position.y - CURSOR_SIZE / 2.0);
+        *visibility = Visibility::Inherited;
+    }
+}
+
+fn playback_hud(
+    playback: Res<Playback>,
+    mut text_query: Query<&mut Text, With<ReplayText>>,
+) {
+    let state = if playback.finished() {
+        " - finished"
+    } else if playback.paused {
+        " - paused"
+    } else {
+        ""
+    };
+    let value = format!(
+        "Replay {}x: {:.1} / {:.1} s{}",
+        PLAYBACK_SPEEDS[playback.speed],
+        playback.clock,
+        playback.replay.duration,
+        state,
+    );
+    for mut text in &mut text_query {
+        text.sections[0].value.clone_from(&value);
+    }
+}
+
+fn playback_cleanup(
+    mut commands: Commands,
+    mut playback: ResMut<Playback>,
+    mut config: ResMut<GameConfiguraiton>,
+) {
+    if let Some(player_config) = playback.player_config.take() {
+        *config = player_config;
+    }
+    commands.remove_resource::<Playback>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RunGoal;
+
+    fn endless_config() -> GameConfiguraiton {
+        GameConfiguraiton {
+            width: 3,
+            height: 3,
+            goal: RunGoal::Endless,
+            mistake_limit: 3,
+            shuffle: crate::ShuffleMode::All,
+            ..default()
+        }
+    }
+
+    // Plays a run the way the game does and records it, every seventh click is a mistake
+    fn record(config: &GameConfiguraiton, seed: u64) -> (SchulteBoard, Vec<ClickOutcome>, Replay) {
+        let mut board = SchulteBoard::new(config.board_rules(), seed);
+        let mut recording = RunRecording::new(seed, config);
+        let mut outcomes = Vec::new();
+        for index in 0.. {
+            let elapsed = Duration::from_millis(250 * index);
+            if board.is_over(elapsed) {
+                break;
+            }
+            let next = board.next_target();
+            let slot = board.cells().iter()
+                .position(|tile| (tile.target == next) != (index % 7 == 6))
+                .unwrap();
+            recording.click(slot, elapsed);
+            let outcome = board.click(slot, elapsed);
+            if matches!(outcome, ClickOutcome::Correct(_)) {
+                board.reshuffle();
+                if board.is_complete() && !board.is_over(elapsed) {
+                    board.next_table();
+                }
+            }
+            outcomes.push(outcome);
+        }
+        (board, outcomes, recording.replay)
+    }
+
+    #[test]
+    fn recorded_runs_replay_to_the_same_board() {
+        let config = endless_config();
+        let (recorded, outcomes, replay) = record(&config, 11);
+        assert!(recorded.found() > 9, "the run spans several tables");
+
+        // Saved and loaded like a replay file
+        let replay: Replay = serde_json::from_str(&serde_json::to_string(&replay).unwrap()).unwrap();
+        assert_eq!(replay.validate(), Ok(()));
+        let mut board = SchulteBoard::new(replay.config.board_rules(), replay.seed);
+        let replayed: Vec<ClickOutcome> = replay.clicks.iter()
+            .map(|click| apply_click(&mut board, click).outcome)
+            .collect();
+        assert_eq!(replayed, outcomes);
+        assert_eq!(board.found(), recorded.found());
+        assert_eq!(board.mistakes(), recorded.mistakes());
+        assert_eq!(board.penalty(), recorded.penalty());
+
+        let duration = replay.clicks.last().unwrap().elapsed();
+        assert_eq!(duration, Duration::from_millis(250 * (outcomes.len() as u64 - 1)));
+        assert_eq!(board.score(duration), recorded.score(duration));
+        assert!(board.is_over(duration));
+    }
+
+    #[test]
+    fn replays_with_bad_clicks_are_rejected() {
+        for elapsed in [-1.0, f32::NAN, f32::INFINITY, 1e30] {
+            let (_, _, mut replay) = record(&endless_config(), 11);
+            replay.clicks[0].elapsed = elapsed;
+            assert!(replay.validate().is_err(), "elapsed {}", elapsed);
+            assert_eq!(replay.clicks[0].elapsed(), Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn replays_with_settings_out_of_range_are_rejected() {
+        let (_, _, replay) = record(&endless_config(), 11);
+        for config in [
+            GameConfiguraiton { width: 1000, ..replay.config.clone() },
+            GameConfiguraiton { timer_duration: f32::NAN, ..replay.config.clone() },
+        ] {
+            let replay = Replay { config, ..replay.clone() };
+            assert!(replay.validate().is_err());
+        }
+    }
+}
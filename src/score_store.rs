@@ -0,0 +1,490 @@
+use bevy::log::warn;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use super::replay::Replay;
+use super::symbols::alphabet_hash;
+
+use super::{
+    project_dirs,
+    GameConfiguraiton,
+    DistractorKind,
+    FixationMode,
+    GameMode,
+    PenaltyPolicy,
+    Perturbation,
+    RunGoal,
+    SequenceRule,
+    ShuffleMode,
+    SymbolSet,
+    DEFAULT_INCORRECT_PENALTY,
+    DEFAULT_PENALTY_PERCENT,
+};
+
+// One finished game, stored as a line of JSON in the score file.
+// Metadata is optional because scores imported from the legacy text format only know the score.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoreRecord {
+    pub timestamp: Option<DateTime<Utc>>,
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    // Raw time on the stopwatch in seconds, without penalty
+    pub time: Option<f32>,
+    // Penalty in seconds added to the time, whole seconds in older records
+    pub penalty: Option<f32>,
+    pub penalty_policy: Option<PenaltyPolicy>,
+    // Cost of a mistake, only for the policies that use it
+    pub incorrect_penalty: Option<u8>,
+    pub penalty_percent: Option<u8>,
+    pub mistakes: Option<u32>,
+    // Final score, in seconds or numbers found depending on `goal`
+    pub score: f32,
+    // Absent for legacy records, which all finished a single table
+    pub goal: Option<RunGoal>,
+    pub time_limit: Option<u16>,
+    pub mistake_limit: Option<u32>,
+    pub mode: Option<GameMode>,
+    // Numbers of classic and memory tables, absent for other modes and legacy records
+    pub sequence: Option<SequenceRule>,
+    pub symbols: Option<SymbolSet>,
+    // Alphabet of `SymbolSet::Custom`, absent for other symbols
+    pub custom_symbols: Option<Vec<String>>,
+    pub shuffle: Option<ShuffleMode>,
+    pub fixation: Option<FixationMode>,
+    pub perturbation: Option<Perturbation>,
+    // Distractor tiles placed in the table and what they showed, if there were any
+    pub distractors: Option<usize>,
+    pub distractor_kind: Option<DistractorKind>,
+    // Seconds the numbers were shown in memory mode
+    pub memory_preview: Option<u8>,
+    // Both change the exercise: the search for the first number is not timed, or the next number is shown
+    pub start_timer_on_first_click: Option<bool>,
+    pub hud_next_number: Option<bool>,
+    // Seed the table was generated from, absent for records from before tables were seeded
+    pub seed: Option<u64>,
+    // File name of the replay in the replay directory, absent when replays were not saved
+    pub replay: Option<String>,
+    // Local date of the daily challenge the run was an attempt at
+    pub daily: Option<NaiveDate>,
+    // Daily challenge attempts after the first one of the day, kept but never ranked
+    #[serde(default)]
+    pub practice: bool,
+    // Runs quit or restarted before the table was finished, kept but never ranked
+    #[serde(default)]
+    pub abandoned: bool,
+}
+
+// Group of scores that are comparable with each other on the leaderboard
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ScoreBucket {
+    // Official daily challenges form a single history, whatever the rules of the day were
+    pub daily: bool,
+    pub goal: Option<RunGoal>,
+    pub time_limit: Option<u16>,
+    pub mistake_limit: Option<u32>,
+    // Records from before penalty policies were introduced all used flat penalties
+    pub penalty_policy: PenaltyPolicy,
+    pub incorrect_penalty: Option<u8>,
+    pub penalty_percent: Option<u8>,
+    pub mode: Option<GameMode>,
+    pub sequence: Option<SequenceRule>,
+    pub symbols: Option<SymbolSet>,
+    // Fingerprint of the custom alphabet, runs with different alphabets are not comparable
+    pub custom_symbols: Option<u64>,
+    pub shuffle: Option<ShuffleMode>,
+    pub fixation: Option<FixationMode>,
+    pub perturbation: Perturbation,
+    pub distractors: Option<usize>,
+    pub distractor_kind: Option<DistractorKind>,
+    pub memory_preview: Option<u8>,
+    // Records from before these were stored are assumed to use the defaults, both off
+    pub start_timer_on_first_click: bool,
+    pub hud_next_number: bool,
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+}
+
+impl fmt::Display for ScoreBucket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.daily {
+            return write!(f, "Daily challenge");
+        }
+        match (self.width, self.height) {
+            (Some(width), Some(height)) => write!(f, "{}x{}", width, height)?,
+            _ => write!(f, "Unknown size")?,
+        }
+        if let Some(mode) = self.mode {
+            write!(f, " {:?}", mode)?;
+        }
+        if let Some(time_limit) = self.time_limit {
+            write!(f, " {} s time attack", time_limit)?;
+        }
+        if let Some(mistake_limit) = self.mistake_limit {
+            write!(f, " endless ({} mistakes)", mistake_limit)?;
+        }
+        if self.penalty_policy != PenaltyPolicy::Flat {
+            write!(f, " {:?} penalty", self.penalty_policy)?;
+        }
+        if let Some(seconds) = self.incorrect_penalty.filter(|seconds| *seconds != DEFAULT_INCORRECT_PENALTY) {
+            write!(f, " ({} s per mistake)", seconds)?;
+        }
+        if let Some(percent) = self.penalty_percent.filter(|percent| *percent != DEFAULT_PENALTY_PERCENT) {
+            write!(f, " ({} % per mistake)", percent)?;
+        }
+        if let Some(preview) = self.memory_preview {
+            write!(f, " ({} s preview)", preview)?;
+        }
+        if self.start_timer_on_first_click {
+            write!(f, " timed from first number")?;
+        }
+        if self.hud_next_number {
+            write!(f, " next number shown")?;
+        }
+        if let Some(sequence) = self.sequence.filter(|sequence| *sequence != SequenceRule::default()) {
+            write!(f, " {}", sequence)?;
+        }
+        if let Some(symbols) = self.symbols.filter(|symbols| *symbols != SymbolSet::Numbers) {
+            write!(f, " {:?}", symbols)?;
+        }
+        if let Some(hash) = self.custom_symbols {
+            write!(f, " #{:04x}", hash as u16)?;
+        }
+        if let (Some(distractors), Some(kind)) = (self.distractors, self.distractor_kind) {
+            write!(f, " +{} {:?}", distractors, kind)?;
+        }
+        if self.perturbation != Perturbation::default() {
+            let Perturbation { rotation, size, color, mirror } = self.perturbation;
+            write!(f, " perturbed {}/{}/{}", rotation, size, color)?;
+            if mirror {
+                write!(f, " mirrored")?;
+            }
+        }
+        if let Some(shuffle) = self.shuffle.filter(|shuffle| *shuffle != ShuffleMode::Off) {
+            write!(f, " reshuffle {:?}", shuffle)?;
+        }
+        match self.fixation {
+            Some(fixation) if fixation != FixationMode::Off => write!(f, " fixation {:?}", fixation),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl ScoreRecord {
+    // Record of a run played right now with the given configuration
+    pub fn from_run(
+        config: &GameConfiguraiton,
+        time: f32,
+        penalty: f32,
+        mistakes: u32,
+        score: f32,
+        seed: u64,
+    ) -> Self {
+        let distractors = config.board_rules().distractor_count();
+        ScoreRecord {
+            timestamp: Some(Utc::now()),
+            width: Some(config.width),
+            height: Some(config.height),
+            time: Some(time),
+            penalty: Some(penalty),
+            penalty_policy: Some(config.penalty_policy),
+            incorrect_penalty: config.penalty_policy.uses_seconds().then_some(config.incorrect_penalty),
+            penalty_percent: config.penalty_policy.uses_percent().then_some(config.penalty_percent),
+            mistakes: Some(mistakes),
+            score,
+            goal: Some(config.goal),
+            time_limit: (config.goal == RunGoal::TimeAttack).then_some(config.time_limit),
+            mistake_limit: (config.goal == RunGoal::Endless).then_some(config.mistake_limit),
+            mode: Some(config.mode),
+            sequence: config.mode.uses_sequence().then(|| config.sequence()),
+            symbols: Some(config.symbols),
+            custom_symbols: (config.symbols == SymbolSet::Custom).then(|| config.custom_symbols.clone()),
+            shuffle: Some(config.shuffle),
+            fixation: Some(config.fixation),
+            perturbation: Some(config.perturbation()),
+            distractors: Some(distractors),
+            distractor_kind: (distractors > 0).then_some(config.distractor_kind),
+            memory_preview: (config.mode == GameMode::Memory).then_some(config.memory_preview),
+            start_timer_on_first_click: Some(config.start_timer_on_first_click),
+            hud_next_number: Some(config.hud_next_number),
+            seed: Some(seed),
+            replay: None,
+            daily: None,
+            practice: false,
+            abandoned: false,
+        }
+    }
+
+    pub fn goal(&self) -> RunGoal {
+        self.goal.unwrap_or_default()
+    }
+
+    pub fn format_score(&self) -> String {
+        self.goal().format_score(self.score)
+    }
+
+    pub fn bucket(&self) -> ScoreBucket {
+        if self.daily.is_some() {
+            return ScoreBucket {
+                daily: true,
+                ..ScoreBucket::default()
+            };
+        }
+        let penalty_policy = self.penalty_policy.unwrap_or_default();
+        ScoreBucket {
+            daily: false,
+            goal: self.goal,
+            time_limit: self.time_limit,
+            mistake_limit: self.mistake_limit,
+            penalty_policy,
+            // Records from before the amount was stored are assumed to use the default one
+            incorrect_penalty: penalty_policy.uses_seconds()
+                .then(|| self.incorrect_penalty.unwrap_or(DEFAULT_INCORRECT_PENALTY)),
+            penalty_percent: penalty_policy.uses_percent()
+                .then(|| self.penalty_percent.unwrap_or(DEFAULT_PENALTY_PERCENT)),
+            mode: self.mode,
+            sequence: self.sequence,
+            symbols: self.symbols,
+            custom_symbols: self.custom_symbols.as_deref().map(alphabet_hash),
+            shuffle: self.shuffle,
+            fixation: self.fixation,
+            // Records from before perturbations existed used plain labels
+            perturbation: self.perturbation.unwrap_or_default(),
+            // Records from before distractors existed had none
+            distractors: self.distractors.or(self.mode.map(|_| 0)),
+            distractor_kind: self.distractor_kind,
+            memory_preview: self.memory_preview,
+            start_timer_on_first_click: self.start_timer_on_first_click.unwrap_or_default(),
+            hud_next_number: self.hud_next_number.unwrap_or_default(),
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    // Parses a line written by older versions, formatted as "12.34 s"
+    fn from_legacy(line: &str) -> Option<Self> {
+        let score = line.trim().strip_suffix('s')?.trim_end().parse().ok()?;
+        Some(ScoreRecord {
+            timestamp: None,
+            width: None,
+            height: None,
+            time: None,
+            penalty: None,
+            penalty_policy: None,
+            incorrect_penalty: None,
+            penalty_percent: None,
+            mistakes: None,
+            score,
+            goal: None,
+            time_limit: None,
+            mistake_limit: None,
+            mode: None,
+            sequence: None,
+            symbols: None,
+            custom_symbols: None,
+            shuffle: None,
+            fixation: None,
+            perturbation: None,
+            distractors: None,
+            distractor_kind: None,
+            memory_preview: None,
+            start_timer_on_first_click: None,
+            hud_next_number: None,
+            seed: None,
+            replay: None,
+            daily: None,
+            practice: false,
+            abandoned: false,
+        })
+    }
+}
+
+const REPLAY_DIR: &str = "replays";
+
+// Owns the location of the score file and the replays next to it, shared by the game over and scoreboard screens
+pub struct ScoreStore {
+    path: PathBuf,
+}
+
+#[derive(Debug)]
+pub enum ScoreStoreError {
+    NoProjectDirs,
+    Io(PathBuf, io::Error),
+    Serialize(serde_json::Error),
+    Parse(PathBuf, serde_json::Error),
+    // A replay file that reads fine but could not have been recorded by the game
+    InvalidReplay(PathBuf, String),
+}
+
+impl fmt::Display for ScoreStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScoreStoreError::NoProjectDirs => write!(f, "unable to get project directories"),
+            ScoreStoreError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            ScoreStoreError::Serialize(error) => write!(f, "{}", error),
+            ScoreStoreError::Parse(path, error) => write!(f, "{}: {}", path.display(), error),
+            ScoreStoreError::InvalidReplay(path, reason) => write!(f, "{}: {}", path.display(), reason),
+        }
+    }
+}
+
+impl ScoreStore {
+    // The score file lives in the data directory, `score_file_path` is relative to it
+    pub fn from_config(config: &GameConfiguraiton) -> Result<Self, ScoreStoreError> {
+        let proj_dirs = project_dirs().ok_or(ScoreStoreError::NoProjectDirs)?;
+        Ok(ScoreStore {
+            path: proj_dirs.data_dir().join(&config.score_file_path),
+        })
+    }
+
+    pub fn append(&self, record: &ScoreRecord) -> Result<(), ScoreStoreError> {
+        let line = serde_json::to_string(record).map_err(ScoreStoreError::Serialize)?;
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|error| self.error(error))?;
+        }
+        let mut score_file = File::options().append(true).create(true).open(&self.path)
+            .map_err(|error| self.error(error))?;
+        writeln!(score_file, "{}", line).map_err(|error| self.error(error))
+    }
+
+    // A missing score file simply means no game has been finished yet.
+    // Legacy text lines are imported and the file is rewritten in the current format.
+    pub fn load(&self) -> Result<Vec<ScoreRecord>, ScoreStoreError> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(self.error(error)),
+        };
+        let mut records = Vec::new();
+        let mut migrated = false;
+        let mut unreadable = false;
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            if let Ok(record) = serde_json::from_str(line) {
+                records.push(record);
+            } else if let Some(record) = ScoreRecord::from_legacy(line) {
+                records.push(record);
+                migrated = true;
+            } else {
+                warn!("Skipping unreadable score record: {}", line);
+                unreadable = true;
+            }
+        }
+        // Never rewrite a file we could not fully understand, it would drop the unreadable lines
+        if migrated && !unreadable {
+            if let Err(error) = self.rewrite(&records) {
+                warn!("Unable to migrate legacy scores: {}", error);
+            }
+        }
+        Ok(records)
+    }
+
+    fn rewrite(&self, records: &[ScoreRecord]) -> Result<(), ScoreStoreError> {
+        let mut contents = String::new();
+        for record in records {
+            contents += &serde_json::to_string(record).map_err(ScoreStoreError::Serialize)?;
+            contents.push('\n');
+        }
+        // Written next to the score file and moved over it, so a failed write never truncates the history
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+        fs::write(&temporary, contents)
+            .and_then(|()| fs::rename(&temporary, &self.path))
+            .map_err(|error| {
+                let _ = fs::remove_file(&temporary);
+                self.error(error)
+            })
+    }
+
+    // Writes a replay and returns the file name to store in its score record
+    pub fn save_replay(&self, replay: &Replay) -> Result<String, ScoreStoreError> {
+        let dir = self.path.with_file_name(REPLAY_DIR);
+        fs::create_dir_all(&dir).map_err(|error| ScoreStoreError::Io(dir.clone(), error))?;
+        let name = format!("{}.json", replay.timestamp.format("%Y%m%d-%H%M%S-%3f"));
+        let path = dir.join(&name);
+        let contents = serde_json::to_string(replay).map_err(ScoreStoreError::Serialize)?;
+        fs::write(&path, contents).map_err(|error| ScoreStoreError::Io(path, error))?;
+        Ok(name)
+    }
+
+    pub fn load_replay(&self, name: &str) -> Result<Replay, ScoreStoreError> {
+        let path = self.path.with_file_name(REPLAY_DIR).join(name);
+        let contents = fs::read_to_string(&path).map_err(|error| ScoreStoreError::Io(path.clone(), error))?;
+        let replay: Replay = serde_json::from_str(&contents)
+            .map_err(|error| ScoreStoreError::Parse(path.clone(), error))?;
+        // Replays may come from other players, nothing in them can be trusted to be in range
+        replay.validate().map_err(|reason| ScoreStoreError::InvalidReplay(path, reason))?;
+        Ok(replay)
+    }
+
+    fn error(&self, error: io::Error) -> ScoreStoreError {
+        ScoreStoreError::Io(self.path.clone(), error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Score file in a directory of its own, removed when the test is done
+    struct TestStore {
+        store: ScoreStore,
+        dir: PathBuf,
+    }
+
+    impl TestStore {
+        fn new(name: &str, contents: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("schulte_table_{}_{}", name, std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("score.txt");
+            fs::write(&path, contents).unwrap();
+            TestStore {
+                store: ScoreStore { path },
+                dir,
+            }
+        }
+
+        fn contents(&self) -> String {
+            fs::read_to_string(&self.store.path).unwrap()
+        }
+    }
+
+    impl Drop for TestStore {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn json_line(score: f32) -> String {
+        serde_json::to_string(&ScoreRecord::from_legacy(&format!("{} s", score)).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn mixed_legacy_and_json_lines_are_loaded_and_migrated() {
+        let test = TestStore::new("migrate", &format!("12.34 s\n{}\n\n9.5 s\n", json_line(10.0)));
+        let records = test.store.load().unwrap();
+        let scores: Vec<f32> = records.iter().map(|record| record.score).collect();
+        assert_eq!(scores, [12.34, 10.0, 9.5]);
+
+        let contents = test.contents();
+        assert_eq!(contents.lines().count(), 3);
+        assert!(contents.lines().all(|line| serde_json::from_str::<ScoreRecord>(line).is_ok()));
+        assert!(!test.dir.join("score.txt.tmp").exists());
+        let reloaded: Vec<f32> = test.store.load().unwrap().iter().map(|record| record.score).collect();
+        assert_eq!(reloaded, scores);
+    }
+
+    #[test]
+    fn files_with_unreadable_lines_are_not_migrated() {
+        let contents = format!("12.34 s\n{}\nnot a score\n", json_line(10.0));
+        let test = TestStore::new("unreadable", &contents);
+        let records = test.store.load().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(test.contents(), contents);
+    }
+}
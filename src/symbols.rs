@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+
+const LATIN: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const CYRILLIC: &str = "АБВГДЕЁЖЗИЙКЛМНОПРСТУФХЦЧШЩЪЫЬЭЮЯ";
+const GREEK: &str = "ΑΒΓΔΕΖΗΘΙΚΛΜΝΞΟΠΡΣΤΥΦΧΨΩ";
+
+// Pairs of characters that are easily mistaken for each other
+const LOOKALIKES: [(char, char); 7] = [
+    ('0', 'O'),
+    ('1', 'I'),
+    ('2', 'Z'),
+    ('3', 'E'),
+    ('5', 'S'),
+    ('6', 'G'),
+    ('8', 'B'),
+];
+
+const ROMAN: [(u16, &str); 13] = [
+    (1000, "M"),
+    (900, "CM"),
+    (500, "D"),
+    (400, "CD"),
+    (100, "C"),
+    (90, "XC"),
+    (50, "L"),
+    (40, "XL"),
+    (10, "X"),
+    (9, "IX"),
+    (5, "V"),
+    (4, "IV"),
+    (1, "I"),
+];
+
+// What is written on the tiles, the order of the tiles is always given by their number
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum SymbolSet {
+    #[default]
+    Numbers,
+    Latin,
+    Cyrillic,
+    Greek,
+    Roman,
+    // Ordered list of symbols provided in the configuration
+    Custom,
+}
+
+impl SymbolSet {
+    pub const ALL: [SymbolSet; 6] = [
+        SymbolSet::Numbers,
+        SymbolSet::Latin,
+        SymbolSet::Cyrillic,
+        SymbolSet::Greek,
+        SymbolSet::Roman,
+        SymbolSet::Custom,
+    ];
+
+    // Label of the tile with the given number, starting at 1.
+    // Alphabets shorter than the table continue with pairs of symbols, like spreadsheet columns.
+    pub fn label(&self, number: u16, custom: &[String]) -> String {
+        match self {
+            SymbolSet::Numbers => number.to_string(),
+            SymbolSet::Latin => spreadsheet_label(number, &symbols_of(LATIN)),
+            SymbolSet::Cyrillic => spreadsheet_label(number, &symbols_of(CYRILLIC)),
+            SymbolSet::Greek => spreadsheet_label(number, &symbols_of(GREEK)),
+            SymbolSet::Roman => roman_numeral(number),
+            SymbolSet::Custom if custom.is_empty() => number.to_string(),
+            SymbolSet::Custom => spreadsheet_label(number, custom),
+        }
+    }
+}
+
+// Variants of a label with one character replaced by its lookalike
+pub fn lookalikes(label: &str) -> Vec<String> {
+    let chars: Vec<char> = label.chars().collect();
+    chars.iter()
+        .enumerate()
+        .filter_map(|(index, &c)| {
+            let replacement = LOOKALIKES.iter().find_map(|&(a, b)| {
+                if c == a {
+                    Some(b)
+                } else if c == b {
+                    Some(a)
+                } else {
+                    None
+                }
+            })?;
+            let mut variant = chars.clone();
+            variant[index] = replacement;
+            Some(variant.into_iter().collect())
+        })
+        .collect()
+}
+
+// Fingerprint of a custom alphabet that stays the same across versions (FNV-1a), so scores can be grouped by it
+pub fn alphabet_hash(symbols: &[String]) -> u64 {
+    let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+    for symbol in symbols {
+        // The separator keeps ["AB"] apart from ["A", "B"]
+        for byte in symbol.bytes().chain([0xFF]) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01B3);
+        }
+    }
+    hash
+}
+
+fn symbols_of(alphabet: &str) -> Vec<String> {
+    alphabet.chars().map(String::from).collect()
+}
+
+fn spreadsheet_label(number: u16, symbols: &[String]) -> String {
+    let base = symbols.len();
+    let mut remaining = number as usize;
+    let mut label = Vec::new();
+    while remaining > 0 {
+        remaining -= 1;
+        label.push(symbols[remaining % base].as_str());
+        remaining /= base;
+    }
+    label.reverse();
+    label.concat()
+}
+
+fn roman_numeral(mut number: u16) -> String {
+    let mut numeral = String::new();
+    for (value, symbol) in ROMAN {
+        while number >= value {
+            numeral += symbol;
+            number -= value;
+        }
+    }
+    numeral
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roman_numerals_use_subtractive_notation() {
+        for (number, numeral) in [
+            (1, "I"),
+            (4, "IV"),
+            (9, "IX"),
+            (14, "XIV"),
+            (40, "XL"),
+            (90, "XC"),
+            (400, "CD"),
+            (1994, "MCMXCIV"),
+        ] {
+            assert_eq!(roman_numeral(number), numeral);
+        }
+    }
+
+    #[test]
+    fn spreadsheet_labels_continue_with_longer_labels() {
+        let latin = symbols_of(LATIN);
+        for (number, label) in [(1, "A"), (26, "Z"), (27, "AA"), (52, "AZ"), (53, "BA"), (702, "ZZ"), (703, "AAA")] {
+            assert_eq!(spreadsheet_label(number, &latin), label);
+        }
+        let binary = symbols_of("01");
+        assert_eq!(spreadsheet_label(3, &binary), "00");
+    }
+
+    #[test]
+    fn custom_symbols_fall_back_to_numbers_when_empty() {
+        assert_eq!(SymbolSet::Custom.label(12, &[]), "12");
+        assert_eq!(SymbolSet::Custom.label(2, &symbols_of("★☆")), "☆");
+    }
+
+    #[test]
+    fn lookalikes_replace_one_character_at_a_time() {
+        assert_eq!(lookalikes("10"), ["I0", "1O"]);
+        assert_eq!(lookalikes("B"), ["8"]);
+        assert!(lookalikes("47").is_empty());
+    }
+
+    #[test]
+    fn alphabet_hash_depends_on_symbols_and_order() {
+        let alphabet = symbols_of("ABC");
+        assert_eq!(alphabet_hash(&alphabet), alphabet_hash(&symbols_of("ABC")));
+        assert_ne!(alphabet_hash(&alphabet), alphabet_hash(&symbols_of("ACB")));
+        assert_ne!(alphabet_hash(&["AB".to_string()]), alphabet_hash(&symbols_of("AB")));
+    }
+}